@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde_json::json;
+use serenity::framework::standard::CommandGroup;
+use serenity::framework::standard::macros::hook;
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+use tracing::error;
+
+use crate::firebase;
+use crate::interactions::Invocation;
+use crate::locale::{self, Localized};
+use crate::{GAMEPLAY_GROUP, ROLLING_GROUP};
+
+/// The command groups a guild can limit to some of its channels. The other groups work
+/// everywhere, so admins can always change the settings and players can always get help.
+pub const RESTRICTABLE: [&CommandGroup; 2] = [&ROLLING_GROUP, &GAMEPLAY_GROUP];
+
+/// Allowed channels of the guilds seen so far, by lowercase group name.
+pub struct ChannelCache;
+
+impl TypeMapKey for ChannelCache {
+    type Value = Arc<RwLock<HashMap<GuildId, HashMap<String, Vec<ChannelId>>>>>;
+}
+
+async fn cache(ctx: &Context) -> Arc<RwLock<HashMap<GuildId, HashMap<String, Vec<ChannelId>>>>> {
+    ctx.data.read().await.get::<ChannelCache>().cloned().expect("Expected ChannelCache in TypeMap.")
+}
+
+/// The names of the groups that can be limited, as used in `!channels`.
+pub fn groups() -> Vec<String> {
+    RESTRICTABLE.iter().map(|group| group.name.to_lowercase()).collect()
+}
+
+/// The limitable group a command belongs to, if any.
+fn group_of(command_name: &str) -> Option<String> {
+    RESTRICTABLE.iter()
+        .find(|group| group.options.commands.iter().any(|command| command.options.names.contains(&command_name)))
+        .map(|group| group.name.to_lowercase())
+}
+
+pub async fn allowed_channels(ctx: &Context, guild_id: GuildId) -> HashMap<String, Vec<ChannelId>> {
+    let cache = cache(ctx).await;
+    if let Some(channels) = cache.read().await.get(&guild_id) {
+        return channels.clone();
+    }
+    let channels: HashMap<String, Vec<ChannelId>> = match firebase::get_guild_settings(guild_id.to_string()).await {
+        Ok(settings) => settings.allowed_channels.into_iter()
+            .map(|(group, channels)| (group, channels.into_iter().map(ChannelId).collect()))
+            .collect(),
+        Err(_) => return HashMap::new(),
+    };
+    cache.write().await.insert(guild_id, channels.clone());
+    channels
+}
+
+/// Saves the channels a group is limited to. An empty list lets the group be used anywhere
+/// again.
+pub async fn set_allowed_channels(ctx: &Context, guild_id: GuildId, group: &str, channels: Vec<ChannelId>) -> Result<(), Localized> {
+    let values: Vec<serde_json::Value> = channels.iter().map(|channel| json!({ "stringValue": channel.to_string() })).collect();
+    firebase::set_guild_setting(guild_id.to_string(), &format!("{}_channels", group), json!({ "arrayValue": { "values": values } })).await?;
+    cache(ctx).await.write().await.entry(guild_id).or_default().insert(group.to_string(), channels);
+    Ok(())
+}
+
+/// Whether a command may run where it was invoked. If it may not, points the author to the
+/// channels it is allowed in.
+pub async fn check(ctx: &Context, invocation: Invocation<'_>, command_name: &str) -> bool {
+    let (guild_id, group) = match (invocation.guild_id(), group_of(command_name)) {
+        (Some(guild_id), Some(group)) => (guild_id, group),
+        _ => return true,
+    };
+    let allowed = allowed_channels(ctx, guild_id).await.remove(&group).unwrap_or_default();
+    if allowed.is_empty() || allowed.contains(&invocation.channel_id()) {
+        return true;
+    }
+    let lang = invocation.locale(ctx).await;
+    let channels = allowed.iter().map(|channel| channel.mention().to_string()).collect::<Vec<String>>().join(", ");
+    if let Err(why) = invocation.reply(ctx, locale::text(&lang, "channels.redirect", &[("command", &command_name), ("channels", &channels)])).await {
+        error!("Could not redirect {} to its channels: {:?}", command_name, why);
+    }
+    false
+}
+
+/// Keeps prefix commands in the channels their group is allowed in.
+#[hook]
+pub async fn before(ctx: &Context, msg: &Message, command_name: &str) -> bool {
+    check(ctx, msg.into(), command_name).await
+}
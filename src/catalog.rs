@@ -0,0 +1,220 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use serenity::prelude::*;
+
+use crate::config;
+use crate::firebase::{self, GeneratedCard};
+use crate::locale::Localized;
+
+/// Every card in the catalog, retired ones included so owners can still name them, kept in
+/// the client data so name lookups and autocomplete do not have to fetch the whole catalog
+/// each time.
+pub struct CatalogCache;
+
+impl TypeMapKey for CatalogCache {
+    type Value = Arc<RwLock<Catalog>>;
+}
+
+#[derive(Default)]
+pub struct Catalog {
+    cards: Arc<Vec<GeneratedCard>>,
+    fetched: Option<Instant>,
+}
+
+/// Returns the cached catalog, fetching it again once it is `CATALOGREFRESH` seconds old.
+pub async fn catalog(ctx: &Context) -> Result<Arc<Vec<GeneratedCard>>, Localized> {
+    let cache = ctx.data.read().await.get::<CatalogCache>().cloned().expect("Expected CatalogCache in TypeMap.");
+    {
+        let catalog = cache.read().await;
+        if catalog.fetched.map(|fetched| fetched.elapsed().as_secs() < config::CATALOGREFRESH).unwrap_or(false) {
+            return Ok(catalog.cards.clone());
+        }
+    }
+    let mut cards = vec![];
+    for category in ["characters", "posters"] {
+        cards.append(&mut firebase::get_catalog(category.to_string()).await?);
+    }
+    let mut catalog = cache.write().await;
+    catalog.cards = Arc::new(cards);
+    catalog.fetched = Some(Instant::now());
+    Ok(catalog.cards.clone())
+}
+
+/// Drops the cached catalog so the next lookup fetches it again, after the catalog changed.
+pub async fn invalidate(ctx: &Context) {
+    let cache = ctx.data.read().await.get::<CatalogCache>().cloned().expect("Expected CatalogCache in TypeMap.");
+    cache.write().await.fetched = None;
+}
+
+fn is_subsequence(query: &str, field: &str) -> bool {
+    let mut chars = field.chars();
+    query.chars().filter(|c| !c.is_whitespace()).all(|c| chars.any(|f| f == c))
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            current.push((previous[j] + cost).min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// How well a lowercase query matches one field, from an exact match down to a typo.
+fn field_score(query: &str, field: &str) -> Option<u32> {
+    let field = field.to_lowercase();
+    if field == query {
+        Some(100)
+    } else if field.starts_with(query) {
+        Some(80)
+    } else if field.split_whitespace().any(|word| word.starts_with(query)) {
+        Some(70)
+    } else if field.contains(query) {
+        Some(60)
+    } else if is_subsequence(query, &field) {
+        Some(40)
+    } else if query.len() >= 4 && field.split_whitespace().chain([field.as_str()]).any(|word| edit_distance(query, word) <= query.len() / 4) {
+        Some(30)
+    } else {
+        None
+    }
+}
+
+/// Scores a card against a query. Names count fully, sets and themes half.
+fn score(card: &GeneratedCard, query: &str) -> Option<u32> {
+    if card.id == query {
+        return Some(200);
+    }
+    let name = field_score(query, &card.name);
+    let other = field_score(query, &card.set).max(field_score(query, &card.theme)).map(|score| score / 2);
+    name.max(other)
+}
+
+/// The cards that best match `query` by name, set or theme, best first.
+pub fn search<'a>(cards: &'a [GeneratedCard], query: &str, limit: usize) -> Vec<&'a GeneratedCard> {
+    let query = query.trim().to_lowercase();
+    let mut matches: Vec<(u32, &GeneratedCard)> = cards.iter().filter_map(|card| score(card, &query).map(|score| (score, card))).collect();
+    matches.sort_by_key(|(score, card)| (std::cmp::Reverse(*score), card.name.to_lowercase()));
+    matches.into_iter().take(limit).map(|(_, card)| card).collect()
+}
+
+/// Finds the card a player meant by an ID or a name. Input that matches more than one card
+/// equally well is answered with a list of the closest cards to choose from.
+pub async fn resolve_card(ctx: &Context, input: &str) -> Result<GeneratedCard, Localized> {
+    let cards = catalog(ctx).await?;
+    resolve(&cards, input).cloned()
+}
+
+/// Picks the card `input` means out of `cards`.
+fn resolve<'a>(cards: &'a [GeneratedCard], input: &str) -> Result<&'a GeneratedCard, Localized> {
+    let input = input.trim().trim_matches('"');
+    if input.is_empty() {
+        return Err(Localized::new("card.missing-query"));
+    }
+    let query = input.to_lowercase();
+    let mut scored: Vec<(u32, &GeneratedCard)> = cards.iter().filter_map(|card| score(card, &query).map(|score| (score, card))).collect();
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    match scored.as_slice() {
+        [] => Err(Localized::new("card.no-match").arg("input", input)),
+        [(_, card)] => Ok(card),
+        [(best, card), (second, _), ..] if *best >= 80 && best > second => Ok(card),
+        _ => {
+            let suggestions: Vec<String> = search(cards, input, config::SUGGESTIONS).iter()
+                .map(|card| format!("`{}` **{}** - {}", card.id, card.name, card.set))
+                .collect();
+            Err(Localized::new("card.ambiguous").arg("input", input).arg("suggestions", suggestions.join("\n")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(id: &str, name: &str, set: &str, theme: &str) -> GeneratedCard {
+        GeneratedCard {
+            set: set.to_string(),
+            theme: theme.to_string(),
+            ..GeneratedCard::sample(id, name)
+        }
+    }
+
+    fn cards() -> Vec<GeneratedCard> {
+        vec![
+            card("100001", "Iron Man", "Marvel", "Action"),
+            card("100002", "Iron Monger", "Marvel", "Action"),
+            card("100003", "Batman", "DC", "Action"),
+            card("100004", "Batgirl", "DC", "Action"),
+            card("100005", "Spider-Man", "Marvel", "Action"),
+        ]
+    }
+
+    #[test]
+    fn edit_distance_counts_single_character_edits() {
+        assert_eq!(edit_distance("batman", "batman"), 0);
+        assert_eq!(edit_distance("batmn", "batman"), 1);
+        assert_eq!(edit_distance("btaman", "batman"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn is_subsequence_keeps_order_and_skips_spaces() {
+        assert!(is_subsequence("irnmn", "iron man"));
+        assert!(is_subsequence("iron man", "iron man"));
+        assert!(!is_subsequence("nori", "iron man"));
+    }
+
+    #[test]
+    fn field_score_ranks_exact_over_prefix_over_typo() {
+        assert_eq!(field_score("iron man", "Iron Man"), Some(100));
+        assert_eq!(field_score("iron", "Iron Man"), Some(80));
+        assert_eq!(field_score("man", "Iron Man"), Some(70));
+        assert_eq!(field_score("ron", "Iron Man"), Some(60));
+        assert_eq!(field_score("irmn", "Iron Man"), Some(40));
+        assert_eq!(field_score("btaman", "Batman"), None);
+        assert_eq!(field_score("batmen", "Batman"), Some(30));
+        assert_eq!(field_score("superman", "Batman"), None);
+    }
+
+    #[test]
+    fn score_weighs_names_over_sets_and_ids_over_both() {
+        let iron_man = card("100001", "Iron Man", "Marvel", "Action");
+        assert_eq!(score(&iron_man, "100001"), Some(200));
+        assert_eq!(score(&iron_man, "iron man"), Some(100));
+        assert_eq!(score(&iron_man, "marvel"), Some(50));
+    }
+
+    #[test]
+    fn resolve_prefers_an_id_or_a_clearly_better_name() {
+        let cards = cards();
+        assert_eq!(resolve(&cards, "100003").map(|card| card.id.as_str()), Ok("100003"));
+        assert_eq!(resolve(&cards, "Iron Man").map(|card| card.id.as_str()), Ok("100001"));
+        assert_eq!(resolve(&cards, "\"spider\"").map(|card| card.id.as_str()), Ok("100005"));
+    }
+
+    #[test]
+    fn resolve_lists_suggestions_for_equally_good_matches() {
+        let cards = cards();
+        match resolve(&cards, "bat") {
+            Err(Localized::Key(key, args)) => {
+                assert_eq!(key, "card.ambiguous");
+                let suggestions = &args.iter().find(|(name, _)| *name == "suggestions").unwrap().1;
+                assert!(suggestions.contains("100003") && suggestions.contains("100004"));
+            },
+            other => panic!("expected an ambiguous match, got {:?}", other.map(|card| &card.id)),
+        }
+    }
+
+    #[test]
+    fn resolve_explains_missing_and_unknown_input() {
+        let cards = cards();
+        assert_eq!(resolve(&cards, "  ").unwrap_err(), Localized::new("card.missing-query"));
+        assert_eq!(resolve(&cards, "zzzz").unwrap_err(), Localized::new("card.no-match").arg("input", "zzzz"));
+    }
+}
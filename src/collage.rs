@@ -0,0 +1,148 @@
+use std::hash::Hasher;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use fnv::FnvHasher;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, ImageOutputFormat, Rgba, RgbaImage};
+use serenity::futures::future::join_all;
+use tokio::fs;
+
+use crate::config;
+use crate::firebase::GeneratedCard;
+
+const TILE_WIDTH: u32 = 200;
+const TILE_HEIGHT: u32 = 300;
+const LABEL_HEIGHT: u32 = 24;
+const GLYPH_SCALE: u32 = 2;
+
+/// 5x7 glyphs for the characters used in labels. Each row is five bits, most significant first.
+fn glyph(c: char) -> [u8; 7] {
+    match c {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        '#' => [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+        'x' => [0x00, 0x00, 0x11, 0x0A, 0x04, 0x0A, 0x11],
+        ' ' => [0x00; 7],
+        _ => [0x1F, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1F],
+    }
+}
+
+fn draw_text(canvas: &mut RgbaImage, text: &str, x: u32, y: u32) {
+    let white = Rgba([255, 255, 255, 255]);
+    for (index, c) in text.chars().enumerate() {
+        let origin_x = x + index as u32 * 6 * GLYPH_SCALE;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..5 {
+                if bits & (0x10 >> column) == 0 {
+                    continue;
+                }
+                for dx in 0..GLYPH_SCALE {
+                    for dy in 0..GLYPH_SCALE {
+                        let px = origin_x + column * GLYPH_SCALE + dx;
+                        let py = y + row as u32 * GLYPH_SCALE + dy;
+                        if px < canvas.width() && py < canvas.height() {
+                            canvas.put_pixel(px, py, white);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Where an image is cached. FNV is fixed by its specification, unlike the standard library's
+/// hasher, so cached files keep their names across Rust releases.
+fn cache_path(url: &str) -> PathBuf {
+    let mut hasher = FnvHasher::default();
+    hasher.write(url.as_bytes());
+    PathBuf::from(config::IMAGECACHEDIR).join(format!("{:016x}", hasher.finish()))
+}
+
+/// Returns the bytes of a card image, downloading it on the first request and reading
+/// it from the disk cache afterwards.
+async fn fetch_image(url: String) -> Option<Vec<u8>> {
+    let path = cache_path(&url);
+    if let Ok(bytes) = fs::read(&path).await {
+        return Some(bytes);
+    }
+    let response = reqwest::get(&url).await.ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    let bytes = response.bytes().await.ok()?.to_vec();
+    if fs::create_dir_all(config::IMAGECACHEDIR).await.is_ok() {
+        let _ = fs::write(&path, &bytes).await;
+    }
+    Some(bytes)
+}
+
+/// Deletes the images cached longest ago until the cache fits in `IMAGECACHESIZE` bytes.
+async fn evict_images() {
+    let mut entries = match fs::read_dir(config::IMAGECACHEDIR).await {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    let mut files = vec![];
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if let Ok(metadata) = entry.metadata().await {
+            if metadata.is_file() {
+                files.push((metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH), metadata.len(), entry.path()));
+            }
+        }
+    }
+    let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+    files.sort();
+    for (_, size, path) in files {
+        if total <= config::IMAGECACHESIZE {
+            break;
+        }
+        if fs::remove_file(&path).await.is_ok() {
+            total -= size;
+        }
+    }
+}
+
+/// Composes the cards into one PNG grid with each card's ID and quantity overlaid.
+/// Cards whose image cannot be loaded are left as an empty tile.
+pub async fn render_grid(cards: &[GeneratedCard]) -> Result<Vec<u8>, String> {
+    let images = join_all(cards.iter().map(|card| fetch_image(card.image.clone()))).await;
+    evict_images().await;
+    let labels: Vec<String> = cards.iter().map(|card| format!("#{} x{}", card.id, card.quantity)).collect();
+
+    tokio::task::spawn_blocking(move || {
+        let columns = if images.len() > 9 { 4 } else { 3 };
+        let rows = images.len().div_ceil(columns).max(1) as u32;
+        let mut canvas = RgbaImage::from_pixel(columns as u32 * TILE_WIDTH, rows * TILE_HEIGHT, Rgba([32, 34, 37, 255]));
+
+        for (index, (bytes, label)) in images.iter().zip(labels.iter()).enumerate() {
+            let x = (index % columns) as u32 * TILE_WIDTH;
+            let y = (index / columns) as u32 * TILE_HEIGHT;
+            let tile = bytes.as_ref().and_then(|bytes| image::load_from_memory(bytes).ok());
+            if let Some(tile) = tile {
+                let tile: DynamicImage = tile.resize_to_fill(TILE_WIDTH, TILE_HEIGHT, FilterType::Triangle);
+                imageops::overlay(&mut canvas, &tile.to_rgba8(), x as i64, y as i64);
+            }
+            for px in x..x + TILE_WIDTH {
+                for py in y + TILE_HEIGHT - LABEL_HEIGHT..y + TILE_HEIGHT {
+                    let pixel = canvas.get_pixel_mut(px, py);
+                    pixel.0 = [pixel.0[0] / 4, pixel.0[1] / 4, pixel.0[2] / 4, 255];
+                }
+            }
+            draw_text(&mut canvas, label, x + 6, y + TILE_HEIGHT - LABEL_HEIGHT + 5);
+        }
+
+        let mut png = Cursor::new(vec![]);
+        DynamicImage::ImageRgba8(canvas).write_to(&mut png, ImageOutputFormat::Png).map_err(|e| e.to_string())?;
+        Ok(png.into_inner())
+    }).await.map_err(|e| e.to_string())?
+}
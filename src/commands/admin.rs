@@ -0,0 +1,605 @@
+use serenity::builder::CreateEmbed;
+use serenity::framework::standard::macros::command;
+use serenity::framework::standard::{CommandResult, Args};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+use serenity::utils;
+use serde_json::json;
+
+use crate::allowlist;
+use crate::catalog;
+use crate::filters;
+use crate::firebase;
+use crate::interactions::{self, Invocation};
+use crate::locale;
+use crate::misc;
+use crate::permissions::{self, Tier};
+use crate::scope::{self, Scope};
+use crate::config;
+
+#[command]
+#[description("Give a card to a player")]
+#[usage("@user (card ID or name)")]
+#[example("@user 100231")]
+pub async fn grant(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_grant(ctx, msg.into(), args).await
+}
+
+pub async fn run_grant(ctx: &Context, invocation: Invocation<'_>, mut args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let user = match args.single::<String>().ok().and_then(|s| misc::parse_user(&s)) {
+        Some(user) if !args.is_empty() => user,
+        _ => {
+            invocation.reply(ctx, locale::text(&lang, "grant.usage", &[])).await?;
+            return Ok(());
+        }
+    };
+    let card_id = match catalog::resolve_card(ctx, args.rest()).await {
+        Ok(card) => card.id,
+        Err(e) => {
+            interactions::send_error(ctx, invocation, e.render(&lang)).await?;
+            return Ok(());
+        }
+    };
+    let scope = invocation.scope(ctx).await?;
+    if let Err(reason) = permissions::require_in_scope(ctx, invocation, "grant", scope).await {
+        invocation.reply(ctx, reason).await?;
+        return Ok(());
+    }
+    match firebase::grant_card(invocation.author().id.to_string(), card_id.clone(), scope.key(user)).await {
+        Ok(_) => {
+            invocation.reply(ctx, locale::text(&lang, "grant.done", &[("card", &card_id), ("user", &user.mention())])).await?;
+        },
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[description("Take a card from a player")]
+#[usage("@user (card ID or name)")]
+#[example("@user 100231")]
+pub async fn revoke(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_revoke(ctx, msg.into(), args).await
+}
+
+pub async fn run_revoke(ctx: &Context, invocation: Invocation<'_>, mut args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let user = match args.single::<String>().ok().and_then(|s| misc::parse_user(&s)) {
+        Some(user) if !args.is_empty() => user,
+        _ => {
+            invocation.reply(ctx, locale::text(&lang, "revoke.usage", &[])).await?;
+            return Ok(());
+        }
+    };
+    let card_id = match catalog::resolve_card(ctx, args.rest()).await {
+        Ok(card) => card.id,
+        Err(e) => {
+            interactions::send_error(ctx, invocation, e.render(&lang)).await?;
+            return Ok(());
+        }
+    };
+    let scope = invocation.scope(ctx).await?;
+    if let Err(reason) = permissions::require_in_scope(ctx, invocation, "revoke", scope).await {
+        invocation.reply(ctx, reason).await?;
+        return Ok(());
+    }
+    match firebase::revoke_card(invocation.author().id.to_string(), card_id.clone(), scope.key(user)).await {
+        Ok(_) => {
+            invocation.reply(ctx, locale::text(&lang, "revoke.done", &[("card", &card_id), ("user", &user.mention())])).await?;
+        },
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[description("The recorded card movements of a player or a card")]
+#[usage("@user, or card (card ID or name)")]
+#[example("@user")]
+#[example("card 100231")]
+pub async fn audit(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_audit(ctx, msg.into(), args).await
+}
+
+pub async fn run_audit(ctx: &Context, invocation: Invocation<'_>, mut args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let target = args.single::<String>().unwrap_or_default();
+    let (title, result) = if target == "card" {
+        let card_id = match catalog::resolve_card(ctx, args.rest()).await {
+            Ok(card) => card.id,
+            Err(e) => {
+                interactions::send_error(ctx, invocation, e.render(&lang)).await?;
+                return Ok(());
+            }
+        };
+        let scope = invocation.scope(ctx).await?;
+        let entries = firebase::ledger_for_card(card_id.clone()).await.map(|entries| entries.into_iter().filter(|entry| scope.covers(entry)).collect());
+        (locale::text(&lang, "audit.card-title", &[("card", &card_id)]), entries)
+    } else if let Some(user) = misc::parse_user(&target) {
+        (locale::text(&lang, "audit.user-title", &[("user", &user.mention())]), firebase::ledger_for_user(invocation.scope(ctx).await?.key(user)).await)
+    } else {
+        invocation.reply(ctx, locale::text(&lang, "audit.usage", &[])).await?;
+        return Ok(());
+    };
+    let entries = match result {
+        Ok(entries) => entries,
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+            return Ok(());
+        }
+    };
+    if entries.is_empty() {
+        invocation.reply(ctx, locale::text(&lang, "audit.none", &[])).await?;
+        return Ok(());
+    }
+    let lines: Vec<String> = entries.iter().take(config::HISTORYLENGTH).map(|entry| misc::describe_ledger_entry(&lang, entry)).collect();
+    let mut embed = CreateEmbed::default();
+    embed.description(lines.join("\n")).footer(|f| f.text(locale::text(&lang, "history.footer", &[("shown", &lines.len()), ("total", &entries.len())])));
+    invocation.send_embed(ctx, title, embed).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Undo a recorded card movement")]
+#[usage("(ledger ID)")]
+pub async fn reverse(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_reverse(ctx, msg.into(), args).await
+}
+
+pub async fn run_reverse(ctx: &Context, invocation: Invocation<'_>, mut args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let entry_id = match args.single::<String>() {
+        Ok(entry_id) => entry_id,
+        Err(_) => {
+            invocation.reply(ctx, locale::text(&lang, "reverse.usage", &[])).await?;
+            return Ok(());
+        }
+    };
+    let scope = invocation.scope(ctx).await?;
+    let entry = match firebase::get_ledger_entry(entry_id.clone()).await {
+        Ok(entry) if scope.covers(&entry) => entry,
+        _ => {
+            invocation.reply(ctx, locale::text(&lang, "reverse.missing", &[("entry", &entry_id)])).await?;
+            return Ok(());
+        }
+    };
+    if let Err(reason) = permissions::require_in_scope(ctx, invocation, "reverse", scope).await {
+        invocation.reply(ctx, reason).await?;
+        return Ok(());
+    }
+    match firebase::reverse_ledger_entry(&entry, invocation.author().id.to_string()).await {
+        Ok(_) => {
+            invocation.reply(ctx, locale::text(&lang, "reverse.done", &[("entry", &entry.id), ("description", &misc::describe_ledger_entry(&lang, &entry))])).await?;
+        },
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[description("Rebuild the circulation counters")]
+pub async fn rebuildstats(ctx: &Context, msg: &Message) -> CommandResult {
+    run_rebuildstats(ctx, msg.into()).await
+}
+
+pub async fn run_rebuildstats(ctx: &Context, invocation: Invocation<'_>) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let typing = invocation.channel_id().start_typing(&ctx.http);
+    let result = firebase::rebuild_circulation().await;
+    if let Ok(typing) = typing {
+        let _ = typing.stop();
+    }
+    match result {
+        Ok(cards) => {
+            invocation.reply(ctx, locale::text(&lang, "rebuildstats.done", &[("count", &cards)])).await?;
+        },
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[description("Choose the channel for moderator reports")]
+#[usage("#channel, or off")]
+#[example("#mod-log")]
+#[example("off")]
+pub async fn modlog(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_modlog(ctx, msg.into(), args).await
+}
+
+pub async fn run_modlog(ctx: &Context, invocation: Invocation<'_>, mut args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let guild_id = invocation.guild_id().unwrap();
+    let target = args.single::<String>().unwrap_or_default();
+    let (value, reply) = if target == "off" {
+        (json!({ "nullValue": null }), locale::text(&lang, "modlog.disabled", &[]))
+    } else if let Some(channel_id) = utils::parse_channel(&target) {
+        (json!({ "stringValue": channel_id.to_string() }), locale::text(&lang, "modlog.set", &[("channel", &channel_id)]))
+    } else {
+        invocation.reply(ctx, locale::text(&lang, "modlog.usage", &[])).await?;
+        return Ok(());
+    };
+    match firebase::set_guild_setting(guild_id.to_string(), "mod_log_channel", value).await {
+        Ok(_) => {
+            invocation.reply(ctx, reply).await?;
+        },
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[description("Show or change the command prefix of this server")]
+#[usage("[set (prefix)|reset]")]
+#[example("set ?")]
+#[example("reset")]
+pub async fn prefix(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_prefix(ctx, msg.into(), args).await
+}
+
+pub async fn run_prefix(ctx: &Context, invocation: Invocation<'_>, mut args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let guild_id = invocation.guild_id().unwrap();
+    let action = args.single::<String>().unwrap_or_default();
+    let new_prefix = match action.as_str() {
+        "set" => match args.single::<String>() {
+            Ok(new_prefix) if new_prefix.chars().count() <= config::MAXPREFIXLENGTH => Some(new_prefix),
+            _ => {
+                invocation.reply(ctx, locale::text(&lang, "prefix.usage", &[("length", &config::MAXPREFIXLENGTH)])).await?;
+                return Ok(());
+            }
+        },
+        "reset" => None,
+        _ => {
+            let current = crate::prefix::guild_prefix(ctx, guild_id).await;
+            invocation.reply(ctx, locale::text(&lang, "prefix.current", &[("prefix", &current)])).await?;
+            return Ok(());
+        }
+    };
+    match crate::prefix::set_guild_prefix(ctx, guild_id, new_prefix).await {
+        Ok(new_prefix) => {
+            invocation.reply(ctx, locale::text(&lang, "prefix.set", &[("prefix", &new_prefix)])).await?;
+        },
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[description("Show or change the language of this server")]
+#[usage("[language code|reset]")]
+#[example("es")]
+#[example("reset")]
+pub async fn serverlanguage(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_serverlanguage(ctx, msg.into(), args).await
+}
+
+pub async fn run_serverlanguage(ctx: &Context, invocation: Invocation<'_>, mut args: Args) -> CommandResult {
+    let guild_id = invocation.guild_id().unwrap();
+    let lang = invocation.locale(ctx).await;
+    let new_locale = match args.single::<String>().map(|s| s.to_lowercase()) {
+        Ok(code) if code == "reset" => None,
+        Ok(code) if locale::supported().contains(&code.as_str()) => Some(code),
+        Ok(code) => {
+            invocation.reply(ctx, locale::text(&lang, "language.unknown", &[("code", &code), ("available", &locale::available())])).await?;
+            return Ok(());
+        },
+        Err(_) => {
+            let current = locale::guild_locale(ctx, guild_id).await.unwrap_or_else(|| config::DEFAULTLOCALE.to_string());
+            invocation.reply(ctx, locale::text(&lang, "serverlanguage.current", &[("language", &locale::describe(&current)), ("available", &locale::available())])).await?;
+            return Ok(());
+        }
+    };
+    let current = new_locale.clone().unwrap_or_else(|| config::DEFAULTLOCALE.to_string());
+    match locale::set_guild_locale(ctx, guild_id, new_locale).await {
+        Ok(_) => {
+            invocation.reply(ctx, locale::text(&lang, "serverlanguage.set", &[("language", &locale::describe(&current))])).await?;
+        },
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[description("Choose whether this server shares the global collections or keeps its own")]
+#[usage("[global|isolated]")]
+#[example("isolated")]
+pub async fn economy(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_economy(ctx, msg.into(), args).await
+}
+
+pub async fn run_economy(ctx: &Context, invocation: Invocation<'_>, mut args: Args) -> CommandResult {
+    let guild_id = invocation.guild_id().unwrap();
+    let lang = invocation.locale(ctx).await;
+    let isolated = match args.single::<String>().map(|s| s.to_lowercase()) {
+        Ok(mode) if mode == "global" => false,
+        Ok(mode) if mode == "isolated" => true,
+        _ => {
+            let key = match invocation.scope(ctx).await? {
+                Scope::Global => "economy.global",
+                Scope::Guild(_) => "economy.isolated",
+            };
+            invocation.reply(ctx, locale::text(&lang, key, &[])).await?;
+            return Ok(());
+        }
+    };
+    match scope::set_isolated(ctx, guild_id, isolated).await {
+        Ok(_) => {
+            let key = if isolated { "economy.set.isolated" } else { "economy.set.global" };
+            invocation.reply(ctx, locale::text(&lang, key, &[])).await?;
+        },
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[description("Limit rolling or gameplay commands to some channels")]
+#[usage("[(group) add|remove (#channel)|(group) clear]")]
+#[example("rolling add #card-rolls")]
+#[example("gameplay clear")]
+pub async fn channels(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_channels(ctx, msg.into(), args).await
+}
+
+pub async fn run_channels(ctx: &Context, invocation: Invocation<'_>, mut args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let guild_id = invocation.guild_id().unwrap();
+    let mut allowed = allowlist::allowed_channels(ctx, guild_id).await;
+    let group = match args.single::<String>().map(|s| s.to_lowercase()) {
+        Ok(group) if allowlist::groups().contains(&group) => group,
+        Ok(_) => {
+            invocation.reply(ctx, locale::text(&lang, "channels.usage", &[("groups", &allowlist::groups().join(", "))])).await?;
+            return Ok(());
+        },
+        Err(_) => {
+            let lines: Vec<String> = allowlist::groups().into_iter().map(|group| {
+                let list = allowed.remove(&group).unwrap_or_default();
+                let where_allowed = if list.is_empty() {
+                    locale::text(&lang, "channels.anywhere", &[])
+                } else {
+                    list.iter().map(|channel| channel.mention().to_string()).collect::<Vec<String>>().join(", ")
+                };
+                format!("**{}**: {}", group, where_allowed)
+            }).collect();
+            invocation.reply(ctx, locale::text(&lang, "channels.current", &[("channels", &lines.join("\n"))])).await?;
+            return Ok(());
+        }
+    };
+    let mut list = allowed.remove(&group).unwrap_or_default();
+    let action = args.single::<String>().unwrap_or_default().to_lowercase();
+    let channel = args.single::<String>().ok().and_then(|s| utils::parse_channel(&s)).map(ChannelId);
+    match (action.as_str(), channel) {
+        ("add", Some(channel)) => {
+            if !list.contains(&channel) {
+                list.push(channel);
+            }
+        },
+        ("remove", Some(channel)) => list.retain(|allowed| *allowed != channel),
+        ("clear", _) => list.clear(),
+        _ => {
+            invocation.reply(ctx, locale::text(&lang, "channels.usage", &[("groups", &allowlist::groups().join(", "))])).await?;
+            return Ok(());
+        }
+    }
+    let reply = if list.is_empty() {
+        locale::text(&lang, "channels.cleared", &[("group", &group)])
+    } else {
+        let mentions = list.iter().map(|channel| channel.mention().to_string()).collect::<Vec<String>>().join(", ");
+        locale::text(&lang, "channels.set", &[("group", &group), ("channels", &mentions)])
+    };
+    match allowlist::set_allowed_channels(ctx, guild_id, &group, list).await {
+        Ok(_) => {
+            invocation.reply(ctx, reply).await?;
+        },
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[description("Choose which roles grant the Moderator, Admin and Owner tiers")]
+#[usage("[(tier) add|remove (@role)|(tier) clear]")]
+#[example("moderator add @Mods")]
+#[example("admin clear")]
+pub async fn roles(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_roles(ctx, msg.into(), args).await
+}
+
+pub async fn run_roles(ctx: &Context, invocation: Invocation<'_>, mut args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let guild_id = invocation.guild_id().unwrap();
+    let mut mapped = permissions::tier_roles(ctx, guild_id).await;
+    let tier = match args.single::<String>() {
+        Ok(name) => match Tier::from_name(&name).filter(|tier| Tier::GUILD.contains(tier)) {
+            Some(tier) => tier,
+            None => {
+                invocation.reply(ctx, locale::text(&lang, "roles.usage", &[])).await?;
+                return Ok(());
+            }
+        },
+        Err(_) => {
+            let lines: Vec<String> = Tier::GUILD.iter().rev().map(|tier| {
+                let list = mapped.remove(tier.as_str()).unwrap_or_default();
+                let holders = if list.is_empty() {
+                    locale::text(&lang, "roles.none", &[])
+                } else {
+                    list.iter().map(|role| role.mention().to_string()).collect::<Vec<String>>().join(", ")
+                };
+                format!("**{}**: {}", locale::text(&lang, &format!("tier.{}", tier.as_str()), &[]), holders)
+            }).collect();
+            invocation.reply(ctx, locale::text(&lang, "roles.current", &[("roles", &lines.join("\n"))])).await?;
+            return Ok(());
+        }
+    };
+    let mut list = mapped.remove(tier.as_str()).unwrap_or_default();
+    let action = args.single::<String>().unwrap_or_default().to_lowercase();
+    let role = args.single::<String>().ok().and_then(|s| utils::parse_role(&s)).map(RoleId);
+    match (action.as_str(), role) {
+        ("add", Some(role)) => {
+            if !list.contains(&role) {
+                list.push(role);
+            }
+        },
+        ("remove", Some(role)) => list.retain(|mapped| *mapped != role),
+        ("clear", _) => list.clear(),
+        _ => {
+            invocation.reply(ctx, locale::text(&lang, "roles.usage", &[])).await?;
+            return Ok(());
+        }
+    }
+    let tier_name = locale::text(&lang, &format!("tier.{}", tier.as_str()), &[]);
+    let reply = if list.is_empty() {
+        locale::text(&lang, "roles.cleared", &[("tier", &tier_name)])
+    } else {
+        let mentions = list.iter().map(|role| role.mention().to_string()).collect::<Vec<String>>().join(", ");
+        locale::text(&lang, "roles.set", &[("tier", &tier_name), ("roles", &mentions)])
+    };
+    match permissions::set_tier_roles(ctx, guild_id, tier, list).await {
+        Ok(_) => {
+            invocation.reply(ctx, reply).await?;
+        },
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
+    }
+    Ok(())
+}
+
+#[command("catalog")]
+#[description("Add cards to the catalog, remove them, or reload it")]
+#[usage("add (category) (card ID) (image URL) name:\"(name)\" set:\"(set)\" theme:\"(theme)\" [rarity:(rarity)] [link:(URL)] | remove (card ID) | refresh")]
+#[example("add posters 100231 https://example.com/100231.jpg name:\"Iron Man\" set:\"Marvel\" theme:Action rarity:rare")]
+#[example("remove 100231")]
+#[example("refresh")]
+pub async fn manage_catalog(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_catalog(ctx, msg.into(), args).await
+}
+
+/// A change to the card catalog, from the arguments of the prefix command or the options of
+/// the slash command.
+pub enum CatalogAction {
+    Add(firebase::GeneratedCard),
+    Remove(String),
+    Refresh,
+}
+
+pub async fn run_catalog(ctx: &Context, invocation: Invocation<'_>, args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let tokens = filters::tokenize(args.rest());
+    let action = match tokens.first().map(|action| action.to_lowercase()).as_deref() {
+        Some("add") => prefix_catalog_card(&lang, &tokens[1..]).map(CatalogAction::Add),
+        Some("remove") => tokens.get(1).cloned().map(CatalogAction::Remove).ok_or_else(|| locale::text(&lang, "catalog.usage", &[])),
+        Some("refresh") => Ok(CatalogAction::Refresh),
+        _ => Err(locale::text(&lang, "catalog.usage", &[])),
+    };
+    match action {
+        Ok(action) => update_catalog(ctx, invocation, action).await,
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e)])).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Applies `action` to the catalog and reloads it.
+pub async fn update_catalog(ctx: &Context, invocation: Invocation<'_>, action: CatalogAction) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let result = match action {
+        CatalogAction::Add(card) => firebase::add_catalog_card(&card).await
+            .map_err(|e| e.render(&lang))
+            .map(|_| locale::text(&lang, "catalog.added", &[("card", &card.name), ("id", &card.id), ("category", &card.category)])),
+        CatalogAction::Remove(card_id) => {
+            let cards = catalog::catalog(ctx).await?;
+            match cards.iter().find(|card| card.id == card_id && !card.retired) {
+                Some(card) => firebase::remove_catalog_card(card.category.clone(), card.id.clone()).await
+                    .map_err(|e| e.render(&lang))
+                    .map(|_| locale::text(&lang, "catalog.removed", &[("card", &card.name), ("id", &card.id)])),
+                None => Err(locale::text(&lang, "catalog.missing", &[("id", &card_id)])),
+            }
+        },
+        CatalogAction::Refresh => Ok(String::new()),
+    };
+    let reply = match result {
+        Ok(reply) => reply,
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e)])).await?;
+            return Ok(());
+        }
+    };
+    catalog::invalidate(ctx).await;
+    let cards = catalog::catalog(ctx).await?;
+    let size = locale::text(&lang, "catalog.size", &[("count", &cards.iter().filter(|card| !card.retired).count())]);
+    invocation.reply(ctx, if reply.is_empty() { size } else { format!("{} {}", reply, size) }).await?;
+    Ok(())
+}
+
+/// Builds a new catalog card from `(category) (card ID) (image URL)` followed by `field:value`
+/// tokens.
+fn prefix_catalog_card(lang: &str, tokens: &[String]) -> Result<firebase::GeneratedCard, String> {
+    let (category, id, image) = match tokens {
+        [category, id, image, ..] => (category, id, image),
+        _ => return Err(locale::text(lang, "catalog.usage", &[])),
+    };
+    let mut fields = vec![];
+    for token in &tokens[3..] {
+        match token.split_once(':') {
+            Some((field, value)) => fields.push((field, value)),
+            None => return Err(locale::text(lang, "catalog.field", &[("field", token)])),
+        }
+    }
+    catalog_card(lang, category, id, image, &fields)
+}
+
+/// Builds a new catalog card from its category, ID, image and named fields. Name, set and
+/// theme are required; rarity and link are optional.
+pub fn catalog_card(lang: &str, category: &str, id: &str, image: &str, fields: &[(&str, &str)]) -> Result<firebase::GeneratedCard, String> {
+    let mut card = firebase::GeneratedCard {
+        name: String::new(),
+        image: image.to_string(),
+        category: misc::get_category(lang, category.to_lowercase())?,
+        set: String::new(),
+        theme: String::new(),
+        id: id.to_string(),
+        quantity: 1,
+        link: String::new(),
+        rarity: config::RARITIES[0].to_string(),
+        locked: false,
+        favorite: false,
+        acquired: 0,
+        retired: false
+    };
+    for (field, value) in fields {
+        match *field {
+            "name" => card.name = value.to_string(),
+            "set" => card.set = value.to_string(),
+            "theme" => card.theme = value.to_string(),
+            "link" => card.link = value.to_string(),
+            "rarity" if config::RARITIES.contains(&value.to_lowercase().as_str()) => card.rarity = value.to_lowercase(),
+            _ => return Err(locale::text(lang, "catalog.field", &[("field", &format!("{}:{}", field, value))])),
+        }
+    }
+    if card.name.is_empty() || card.set.is_empty() || card.theme.is_empty() {
+        return Err(locale::text(lang, "catalog.usage", &[]));
+    }
+    Ok(card)
+}
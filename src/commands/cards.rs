@@ -1,13 +1,20 @@
-use std::time::Duration;
-
+use chrono::{DateTime, Utc};
+use serenity::builder::CreateEmbed;
 use serenity::framework::standard::macros::command;
 use serenity::framework::standard::{CommandResult, Args};
 use serenity::model::prelude::*;
 use serenity::prelude::*;
+use serde_json::json;
 
+use crate::catalog;
+use crate::collage;
+use crate::filters;
 use crate::firebase;
-use crate::interactions;
+use crate::interactions::{self, Invocation};
+use crate::locale;
 use crate::misc;
+use crate::safeguards;
+use crate::scope;
 use crate::config;
 
 #[command]
@@ -20,32 +27,47 @@ pub async fn rick(ctx: &Context, msg: &Message) -> CommandResult {
 
 #[command]
 #[aliases("r")]
+#[description("Roll a new card")]
+#[usage("(category)")]
+#[example("characters")]
+#[example("p")]
 pub async fn roll(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_roll(ctx, msg.into(), args).await
+}
+
+pub async fn run_roll(ctx: &Context, invocation: Invocation<'_>, args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
     //  Parse args
     let passed_args = args.rest().to_string();
     let mut split_args = passed_args.split_whitespace();
     let category_option = split_args.next();
     if category_option.is_none() {
-        msg.reply(ctx, "You must supply a category when you use this function. Examples: c, characters, p, posters").await?;
+        invocation.reply(ctx, locale::text(&lang, "category.missing", &[])).await?;
         return Ok(());
     }
 
     //  Parse category from string
-    let category_result = misc::get_category(category_option.unwrap().to_string().to_lowercase());
+    let category_result = misc::get_category(&lang, category_option.unwrap().to_string().to_lowercase());
     let category: String = match category_result {
         Ok(s) => {
             s
         },
         Err(e) => {
-            interactions::send_error(ctx, msg, e).await?;
+            interactions::send_error(ctx, invocation, e).await?;
             return Ok(());
         }
     };
 
     // Check for duration
-    let checked_time = firebase::check_roll_time(msg.author.id.to_string()).await.expect("Failed to get last rolled time.");
-    if !checked_time {
-        msg.reply(ctx, "You can only roll once every 15 minutes!").await?;
+    let checked_time = match firebase::check_roll_time(invocation.player(ctx).await?).await {
+        Ok(checked_time) => checked_time,
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+            return Ok(());
+        }
+    };
+    if let Some(ready) = checked_time {
+        invocation.reply(ctx, locale::text(&lang, "roll.cooldown", &[("minutes", &config::ROLLTIME), ("ready", &ready.timestamp())])).await?;
         return Ok(());
     }
 
@@ -55,21 +77,21 @@ pub async fn roll(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     //  Send the rolled card to the user
     match generated_card {
         Ok(card) => {
-            let _saved_status = firebase::save_card(msg.author.id.to_string(), card.id.clone()).await;
-            if card.link.is_empty() {
-                if let Err(why) = msg.channel_id.send_message(&ctx.http, |m| {
-                    m.content(format!("{} rolled:", msg.author.mention())).embed(|e| e.title(card.name).description(card.set).footer(|f| f.text(format!("{} - ID: {}", card.theme, card.id))).image(card.image))
-                }).await {
-                    println!("Error sending message: {:?}", why);
-                }
-            } else {
-                msg.channel_id.send_message(&ctx.http, |m| {
-                    m.content(format!("{} rolled:", msg.author.mention())).embed(|e| e.title(card.name).url(card.link).description(card.set).footer(|f| f.text(format!("{} - ID: {}", card.theme, card.id))).image(card.image))
-                }).await?;
+            if let Err(e) = firebase::save_card(invocation.player(ctx).await?, card.id.clone()).await {
+                interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+                return Ok(());
+            }
+            let mut embed = CreateEmbed::default();
+            embed.title(card.name).description(card.set).footer(|f| f.text(locale::text(&lang, "card.footer", &[("theme", &card.theme), ("id", &card.id)]))).image(card.image);
+            if !card.link.is_empty() {
+                embed.url(card.link);
+            }
+            if let Err(why) = invocation.send_embed(ctx, locale::text(&lang, "roll.rolled", &[("user", &invocation.author().mention())]), embed).await {
+                println!("Error sending message: {:?}", why);
             }
         },
         Err(e) => {
-            if let Err(why) = msg.channel_id.say(&ctx.http, format!("Error: {}", e)).await {
+            if let Err(why) = invocation.say(ctx, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await {
                 println!("Error sending message: {:?}", why);
             }
         }
@@ -77,9 +99,127 @@ pub async fn roll(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     Ok(())
 }
 
+fn inventory_footer(lang: &str, card: &firebase::GeneratedCard) -> String {
+    let mut footer = locale::text(lang, "inventory.footer", &[("theme", &card.theme), ("id", &card.id), ("quantity", &card.quantity)]);
+    if card.favorite {
+        footer.push_str(&locale::text(lang, "inventory.footer-favorite", &[]));
+    }
+    if card.locked {
+        footer.push_str(&locale::text(lang, "inventory.footer-locked", &[]));
+    }
+    footer
+}
+
+/// How many pages an inventory of `count` cards takes: one per card in `view` mode, or one
+/// per `LISTPAGESIZE` cards in `list` mode.
+fn inventory_page_count(count: usize, list_mode: bool) -> usize {
+    if list_mode {
+        count.div_ceil(config::LISTPAGESIZE)
+    } else {
+        count
+    }
+}
+
+/// The rows of one `list` page: ID, name, set and quantity of each card, marked when it is a
+/// favorite or locked.
+fn list_rows(inventory: &[firebase::GeneratedCard], page: usize) -> Vec<String> {
+    inventory.iter().skip(page * config::LISTPAGESIZE).take(config::LISTPAGESIZE).map(|card| {
+        let mut row = format!("`{}` **{}** - {} x{}", card.id, card.name, card.set, card.quantity);
+        if card.favorite {
+            row.push_str(" ⭐");
+        }
+        if card.locked {
+            row.push_str(" 🔒");
+        }
+        row
+    }).collect()
+}
+
+/// Renders one page of an inventory: a single card with its image in `view` mode, or
+/// `LISTPAGESIZE` rows of ID, name, set and quantity in `list` mode.
+fn inventory_page<'a>(lang: &str, e: &'a mut CreateEmbed, inventory: &[firebase::GeneratedCard], page: usize, list_mode: bool) -> &'a mut CreateEmbed {
+    if list_mode {
+        return e.description(list_rows(inventory, page).join("\n")).footer(|f| f.text(locale::text(lang, "inventory.list-footer", &[("count", &inventory.len())])));
+    }
+    let card = &inventory[page];
+    e.title(&card.name).description(&card.set).footer(|f| f.text(inventory_footer(lang, card))).image(&card.image);
+    if !card.link.is_empty() {
+        e.url(&card.link);
+    }
+    e
+}
+
+/// Decides whether the author of `invocation` may see `owner`'s collection, following the owner's
+/// privacy setting. Everyone may see their own collection.
+async fn check_privacy(ctx: &Context, invocation: Invocation<'_>, owner: UserId) -> Result<(), String> {
+    if owner == invocation.author().id {
+        return Ok(());
+    }
+    let lang = invocation.locale(ctx).await;
+    let settings = firebase::get_user_settings(owner.to_string()).await.map_err(|e| e.render(&lang))?;
+    match settings.privacy {
+        firebase::Privacy::Public => Ok(()),
+        firebase::Privacy::Guild => match invocation.guild_id() {
+            Some(guild_id) if guild_id.member(ctx, owner).await.is_ok() => Ok(()),
+            _ => Err(locale::text(&lang, "privacy.guild-only", &[("user", &owner.mention())])),
+        },
+        firebase::Privacy::Private => Err(locale::text(&lang, "privacy.private", &[("user", &owner.mention())])),
+    }
+}
+
+/// Whether the player behind a scope key lets the author of `invocation` see their collection,
+/// and so whether statistics may name them.
+async fn shows_collection(ctx: &Context, invocation: Invocation<'_>, key: &str) -> bool {
+    match scope::user_of(key).parse() {
+        Ok(id) => check_privacy(ctx, invocation, UserId(id)).await.is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// How an inventory is shown: one card at a time with its artwork, a compact list or a
+/// picture grid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    View,
+    List,
+    Grid,
+}
+
+impl Layout {
+    pub fn from_name(input: &str) -> Option<Layout> {
+        match input {
+            "view" => Some(Layout::View),
+            "list" => Some(Layout::List),
+            "grid" => Some(Layout::Grid),
+            _ => None,
+        }
+    }
+}
+
+/// The part of a collection to show, from the arguments of the prefix command or the options
+/// of the slash command.
+pub struct InventoryRequest {
+    pub category: String,
+    pub owner: UserId,
+    pub filter: filters::CardFilter,
+    pub sort: Option<filters::CardSort>,
+    pub layout: Layout,
+    pub start_page: usize,
+}
+
 #[command]
 #[aliases("i")]
+#[description("Browse a card collection")]
+#[usage("(category) [@user] [set:(text)] [theme:(text)] [name:(text)] [dupes] [favorites] [locked] [sort:(field)] [asc|desc] [view|list|grid] [page:(number)]")]
+#[example("characters")]
+#[example("c @user set:\"Season 1\" sort:rarity")]
+#[example("p dupes list")]
 pub async fn inventory(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_inventory(ctx, msg.into(), args).await
+}
+
+pub async fn run_inventory(ctx: &Context, invocation: Invocation<'_>, args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
     let character_category_alternate: Vec<String> = vec![
         "characters".to_string(),
         "character".to_string(),
@@ -98,7 +238,7 @@ pub async fn inventory(ctx: &Context, msg: &Message, args: Args) -> CommandResul
     let mut split_args = passed_args.split_whitespace();
     let category_option = split_args.next();
     if category_option.is_none() {
-        msg.reply(ctx, "You must supply a category when you use this function. Examples: c, characters, p, posters").await?;
+        invocation.reply(ctx, locale::text(&lang, "category.missing", &[])).await?;
         return Ok(());
     }
     let mut category = category_option.unwrap().to_string().to_lowercase();
@@ -108,120 +248,903 @@ pub async fn inventory(ctx: &Context, msg: &Message, args: Args) -> CommandResul
         category = "posters".to_string();
     }
     if !(category == "characters" || category == "posters") {
-        interactions::send_error(ctx, msg, format!("Did not recognize category: {}. Valid categories include \"characters\" and \"posters\".", category)).await?;
+        interactions::send_error(ctx, invocation, locale::text(&lang, "category.unknown", &[("category", &category)])).await?;
         return Ok(());
     }
 
-    let inventory_status = firebase::check_inventory_time(msg.author.id.to_string()).await;
+    let (filter, unknown) = filters::CardFilter::parse(filters::tokenize(&split_args.collect::<Vec<&str>>().join(" ")));
+    let (sort, unknown) = match filters::CardSort::parse(unknown) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            interactions::send_error(ctx, invocation, e.render(&lang)).await?;
+            return Ok(());
+        }
+    };
+    let mut request = InventoryRequest {
+        category,
+        owner: invocation.author().id,
+        filter,
+        sort,
+        layout: Layout::View,
+        start_page: 0,
+    };
+    for option in unknown {
+        let lowercase = option.to_lowercase();
+        if let Some(user) = option.starts_with("<@").then(|| misc::parse_user(&option)).flatten() {
+            request.owner = user;
+        } else if let Some(layout) = Layout::from_name(&lowercase) {
+            request.layout = layout;
+        } else if let Some(page) = lowercase.strip_prefix("page:").and_then(|p| p.parse::<usize>().ok()) {
+            request.start_page = page.saturating_sub(1);
+        } else {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "inventory.unknown-option", &[("option", &option)])).await?;
+            return Ok(());
+        }
+    }
+    show_inventory(ctx, invocation, request).await
+}
+
+/// Shows the part of a collection `request` asks for, after the privacy and cooldown checks.
+pub async fn show_inventory(ctx: &Context, invocation: Invocation<'_>, request: InventoryRequest) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let InventoryRequest { category, owner, filter, sort, layout, start_page } = request;
+    if let Err(e) = check_privacy(ctx, invocation, owner).await {
+        interactions::send_error(ctx, invocation, e).await?;
+        return Ok(());
+    }
+
+    let inventory_status = firebase::check_inventory_time(invocation.player(ctx).await?).await;
     match inventory_status {
-        Ok(b) => {
-            if !b {
-                msg.reply(ctx, format!("Can only run the inventory command every {} minutes.", config::INVTIME)).await?;
+        Ok(Some(ready)) => {
+            invocation.reply(ctx, locale::text(&lang, "inventory.cooldown", &[("minutes", &config::INVTIME), ("ready", &ready.timestamp())])).await?;
+            return Ok(());
+        },
+        Ok(None) => {},
+        Err(e) => {
+            invocation.reply(ctx, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+            return Ok(());
+        }
+    }
+
+    let inventory = firebase::fetch_inventory(invocation.scope(ctx).await?.key(owner), category).await?;
+    if inventory.is_empty() {
+        if owner == invocation.author().id {
+            invocation.reply(ctx, locale::text(&lang, "inventory.empty-own", &[])).await?;
+        } else {
+            invocation.reply(ctx, locale::text(&lang, "inventory.empty-other", &[("user", &owner.mention())])).await?;
+        }
+        return Ok(());
+    }
+    let mut inventory = filter.apply(inventory);
+    if inventory.is_empty() {
+        invocation.reply(ctx, locale::text(&lang, "inventory.no-matches", &[])).await?;
+        return Ok(());
+    }
+    let sort = match sort {
+        Some(sort) => Some(sort),
+        None => firebase::get_user_settings(invocation.author().id.to_string()).await?.default_sort.and_then(|s| filters::CardSort::from_setting(&s)),
+    };
+    if let Some(sort) = sort {
+        sort.apply(&mut inventory);
+    }
+    if layout == Layout::Grid {
+        let page_count = inventory.len().div_ceil(config::GRIDPAGESIZE);
+        let page = start_page.min(page_count - 1);
+        let cards = &inventory[page * config::GRIDPAGESIZE..((page + 1) * config::GRIDPAGESIZE).min(inventory.len())];
+        let typing = invocation.channel_id().start_typing(&ctx.http);
+        let grid = collage::render_grid(cards).await;
+        if let Ok(typing) = typing {
+            let _ = typing.stop();
+        }
+        match grid {
+            Ok(png) => {
+                invocation.send_file(ctx, locale::text(&lang, "inventory.page-title", &[("user", &owner.mention()), ("page", &(page + 1)), ("count", &page_count)]), png, "inventory.png").await?;
+            },
+            Err(e) => {
+                interactions::send_error(ctx, invocation, locale::text(&lang, "inventory.grid-error", &[("error", &e)])).await?;
+            }
+        }
+        return Ok(());
+    }
+
+    let list_mode = layout == Layout::List;
+    let page_count = inventory_page_count(inventory.len(), list_mode);
+    let title = if list_mode { "inventory.page-title" } else { "inventory.card-title" };
+    let paginator = interactions::Paginator::new(invocation.author().id, page_count, |page| {
+        let mut embed = CreateEmbed::default();
+        inventory_page(&lang, &mut embed, &inventory, page, list_mode);
+        (locale::text(&lang, title, &[("user", &owner.mention()), ("page", &(page + 1)), ("count", &page_count)]), embed)
+    });
+    paginator.start_at(start_page).send(ctx, invocation).await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("v")]
+#[description("Look at any card")]
+#[usage("(card ID or name)")]
+#[example("100231")]
+pub async fn view(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_view(ctx, msg.into(), args).await
+}
+
+pub async fn run_view(ctx: &Context, invocation: Invocation<'_>, args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let card = match catalog::resolve_card(ctx, args.rest()).await {
+        Ok(card) => card,
+        Err(e) => {
+            interactions::send_error(ctx, invocation, e.render(&lang)).await?;
+            return Ok(());
+        }
+    };
+    let owned = firebase::owned_quantity(invocation.player(ctx).await?, card.id.clone()).await?;
+    let copies = invocation.scope(ctx).await?.circulation(firebase::get_card_stats(card.id.clone()).await?).total;
+    let owned_text = if owned == 0 { locale::text(&lang, "view.not-owned", &[]) } else { locale::text(&lang, "view.owned", &[("count", &owned)]) };
+    let mut embed = CreateEmbed::default();
+    embed.title(&card.name)
+        .description(&card.set)
+        .field(locale::text(&lang, "view.category", &[]), &card.category, true)
+        .field(locale::text(&lang, "view.theme", &[]), &card.theme, true)
+        .field(locale::text(&lang, "view.rarity", &[]), &card.rarity, true)
+        .field(locale::text(&lang, "view.you", &[]), owned_text, true)
+        .field(locale::text(&lang, "view.circulation", &[]), copies, true)
+        .footer(|f| f.text(locale::text(&lang, "card.id", &[("id", &card.id)])))
+        .image(&card.image);
+    if !card.link.is_empty() {
+        embed.url(&card.link);
+    }
+    invocation.send_embed(ctx, "", embed).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Circulation statistics for a card")]
+#[usage("(card ID or name)")]
+#[example("100231")]
+pub async fn cardstats(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_cardstats(ctx, msg.into(), args).await
+}
+
+pub async fn run_cardstats(ctx: &Context, invocation: Invocation<'_>, args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let card = match catalog::resolve_card(ctx, args.rest()).await {
+        Ok(card) => card,
+        Err(e) => {
+            interactions::send_error(ctx, invocation, e.render(&lang)).await?;
+            return Ok(());
+        }
+    };
+    let stats = invocation.scope(ctx).await?.circulation(firebase::get_card_stats(card.id.clone()).await?);
+    // Players who keep their collection from the author are counted but never named.
+    let mut top_holders: Vec<String> = vec![];
+    for (key, count) in &stats.holders {
+        if top_holders.len() == config::TOPHOLDERS {
+            break;
+        }
+        if shows_collection(ctx, invocation, key).await {
+            top_holders.push(format!("{}. {} - {}", top_holders.len() + 1, scope::mention(key), count));
+        }
+    }
+    let first_roller = match &stats.first_roller {
+        Some(key) if shows_collection(ctx, invocation, key).await => scope::mention(key),
+        Some(_) => locale::text(&lang, "cardstats.hidden", &[]),
+        None => locale::text(&lang, "unknown", &[]),
+    };
+    let first_roll = match (&stats.first_roller, stats.first_rolled) {
+        (Some(_), Some(rolled)) => format!("{} <t:{}:R>", first_roller, rolled.timestamp()),
+        _ => first_roller,
+    };
+    let mut embed = CreateEmbed::default();
+    embed.title(locale::text(&lang, "cardstats.title", &[("card", &card.name)]))
+        .thumbnail(&card.image)
+        .field(locale::text(&lang, "cardstats.copies", &[]), stats.total, true)
+        .field(locale::text(&lang, "cardstats.owners", &[]), stats.holders.len(), true)
+        .field(locale::text(&lang, "cardstats.first-roll", &[]), first_roll, true)
+        .field(locale::text(&lang, "cardstats.top-holders", &[]), if top_holders.is_empty() { locale::text(&lang, "cardstats.no-holders", &[]) } else { top_holders.join("\n") }, false)
+        .footer(|f| f.text(locale::text(&lang, "card.id", &[("id", &card.id)])));
+    invocation.send_embed(ctx, "", embed).await?;
+    Ok(())
+}
+
+/// Cards in `from` that `to` does not own, duplicates first, as list rows.
+fn missing_cards(from: &[firebase::GeneratedCard], to: &[firebase::GeneratedCard]) -> Vec<String> {
+    let mut missing: Vec<&firebase::GeneratedCard> = from.iter().filter(|card| !to.iter().any(|owned| owned.id == card.id)).collect();
+    missing.sort_by_key(|card| std::cmp::Reverse(card.quantity));
+    missing.iter().map(|card| format!("`{}` **{}** - {} x{}", card.id, card.name, card.set, card.quantity)).collect()
+}
+
+#[command]
+#[description("Compare your collection with another player's")]
+#[usage("@user [category]")]
+#[example("@user characters")]
+pub async fn compare(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_compare(ctx, msg.into(), args).await
+}
+
+pub async fn run_compare(ctx: &Context, invocation: Invocation<'_>, args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let passed_args = args.rest().to_string();
+    let mut split_args = passed_args.split_whitespace();
+    let other = match split_args.next().and_then(misc::parse_user) {
+        Some(other) if other != invocation.author().id => other,
+        _ => {
+            invocation.reply(ctx, locale::text(&lang, "compare.usage", &[])).await?;
+            return Ok(());
+        }
+    };
+    if let Err(e) = check_privacy(ctx, invocation, other).await {
+        interactions::send_error(ctx, invocation, e).await?;
+        return Ok(());
+    }
+    let categories = match split_args.next() {
+        Some(category) => match misc::get_category(&lang, category.to_lowercase()) {
+            Ok(category) => vec![category],
+            Err(e) => {
+                interactions::send_error(ctx, invocation, e).await?;
                 return Ok(());
             }
         },
-        Err(e) => {
-            msg.reply(ctx, format!("Error: {}", e)).await?;
+        None => vec!["characters".to_string(), "posters".to_string()],
+    };
+
+    let mut mine = vec![];
+    let mut theirs = vec![];
+    for category in categories {
+        mine.append(&mut firebase::fetch_inventory(invocation.player(ctx).await?, category.clone()).await?);
+        theirs.append(&mut firebase::fetch_inventory(invocation.scope(ctx).await?.key(other), category).await?);
+    }
+
+    let mut pages: Vec<(String, Vec<String>)> = vec![];
+    for (title, rows) in [
+        (locale::text(&lang, "compare.they-have", &[]), missing_cards(&theirs, &mine)),
+        (locale::text(&lang, "compare.you-have", &[]), missing_cards(&mine, &theirs)),
+    ] {
+        if rows.is_empty() {
+            pages.push((title.clone(), vec![locale::text(&lang, "compare.nothing", &[])]));
+        }
+        for chunk in rows.chunks(config::LISTPAGESIZE) {
+            pages.push((title.clone(), chunk.to_vec()));
+        }
+    }
+
+    let page_count = pages.len();
+    let header = locale::text(&lang, "compare.header", &[("user", &invocation.author().mention()), ("other", &other.mention())]);
+    let paginator = interactions::Paginator::new(invocation.author().id, page_count, |page| {
+        let (title, rows) = &pages[page];
+        let mut embed = CreateEmbed::default();
+        embed.title(title).description(rows.join("\n")).footer(|f| f.text(locale::text(&lang, "page", &[("page", &(page + 1)), ("count", &page_count)])));
+        (header.clone(), embed)
+    });
+    paginator.send(ctx, invocation).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Show or change who can browse your collection")]
+#[usage("[public|guild|private]")]
+#[example("guild")]
+pub async fn privacy(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_privacy(ctx, msg.into(), args).await
+}
+
+pub async fn run_privacy(ctx: &Context, invocation: Invocation<'_>, mut args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let privacy = match args.single::<String>().ok().and_then(|s| firebase::Privacy::from_name(&s.to_lowercase())) {
+        Some(privacy) => privacy,
+        None => {
+            let current = firebase::get_user_settings(invocation.author().id.to_string()).await?.privacy;
+            invocation.reply(ctx, locale::text(&lang, "privacy.current", &[("privacy", &current.as_str())])).await?;
             return Ok(());
         }
+    };
+    match firebase::set_user_setting(invocation.author().id.to_string(), "privacy", json!({ "stringValue": privacy.as_str() })).await {
+        Ok(_) => {
+            invocation.reply(ctx, locale::text(&lang, "privacy.set", &[("privacy", &privacy.as_str())])).await?;
+        },
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
     }
+    Ok(())
+}
 
+#[command]
+#[description("Choose how your inventory is sorted by default")]
+#[usage("(name|set|theme|quantity|recent|rarity) [asc|desc], or off")]
+#[example("rarity desc")]
+#[example("off")]
+pub async fn defaultsort(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_defaultsort(ctx, msg.into(), args).await
+}
 
-    let inventory = firebase::fetch_inventory(msg.author.id.to_string(), category).await;
-    if inventory.is_empty() {
-        msg.reply(ctx, "You do not have any cards! Roll for them using !r (category).").await?;
+pub async fn run_defaultsort(ctx: &Context, invocation: Invocation<'_>, args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let mut tokens = filters::tokenize(args.rest());
+    if tokens.is_empty() {
+        invocation.reply(ctx, locale::text(&lang, "defaultsort.usage", &[])).await?;
         return Ok(());
     }
-    let mut card_index = 0;
-    let mut timer = Duration::from_secs(300);
-    let length = inventory.len() - 1;
-    let emojis = [
-        ReactionType::from('⬅'),
-        ReactionType::from('➡'),
-    ];
-    let forward_emoji = [
-        ReactionType::from('➡'),
-    ];
-    let backward_emoji = [
-        ReactionType::from('⬅'),
-    ];
-    let mut message = msg.channel_id.send_message(&ctx.http, |m| {
-        if inventory[card_index].link.is_empty() {
-            m.content(format!("{}'s inventory: Card {}/{}", msg.author.mention(), 1, length + 1)).embed(|e| e.title(&inventory[0].name).description(&inventory[0].set).footer(|f| f.text(format!("{} - ID: {} - Quantity: {}", &inventory[0].theme, &inventory[0].id, &inventory[0].quantity))).image(&inventory[0].image))
-        } else {
-            m.content(format!("{}'s inventory: Card {}/{}", msg.author.mention(), 1, length + 1)).embed(|e| e.title(&inventory[0].name).url(&inventory[0].link).description(&inventory[0].set).footer(|f| f.text(format!("{} - ID: {} - Quantity: {}", &inventory[0].theme, &inventory[0].id, &inventory[0].quantity))).image(&inventory[0].image))
-        }
-    }).await.expect("Failed to send message");
-
-    let mut selection = interactions::reaction_prompt(ctx, &message, &msg.author, &forward_emoji, 30.0).await?;
-
-    let mut forward = true;
-    let mut backward = false;
-
-    while !timer.is_zero() {
-        let (index, _) = selection;
-        if (index == 0 && card_index > 0) || backward {
-            backward = false;
-            card_index -= 1;
-            message.edit(&ctx.http, |m| {
-                if inventory[card_index].link.is_empty() {
-                    m.content(format!("{}'s inventory: Card {}/{}", msg.author.mention(), card_index + 1, length + 1)).embed(|e| e.title(&inventory[card_index].name).description(&inventory[card_index].set).footer(|f| f.text(format!("{} - ID: {} - Quantity: {}", &inventory[card_index].theme, &inventory[card_index].id, &inventory[card_index].quantity))).image(&inventory[card_index].image))
-                } else {
-                    m.content(format!("{}'s inventory: Card {}/{}", msg.author.mention(), card_index + 1, length + 1)).embed(|e| e.title(&inventory[card_index].name).url(&inventory[card_index].link).description(&inventory[card_index].set).footer(|f| f.text(format!("{} - ID: {} - Quantity: {}", &inventory[card_index].theme, &inventory[card_index].id, &inventory[card_index].quantity))).image(&inventory[card_index].image))
-                }
-            }).await?;
-            message.delete_reactions(ctx).await?;
-            if card_index == 0 {
-                selection = interactions::reaction_prompt(ctx, &message, &msg.author, &forward_emoji, 30.0).await?;
-                forward = true;
-            } else {
-                selection = interactions::reaction_prompt(ctx, &message, &msg.author, &emojis, 30.0).await?;
+    let value = if tokens[0] == "off" {
+        json!({ "nullValue": null })
+    } else {
+        tokens[0] = format!("sort:{}", tokens[0]);
+        match filters::CardSort::parse(tokens) {
+            Ok((Some(sort), rest)) if rest.is_empty() => json!({ "stringValue": sort.to_setting() }),
+            Ok(_) => {
+                invocation.reply(ctx, locale::text(&lang, "defaultsort.usage", &[])).await?;
+                return Ok(());
+            },
+            Err(e) => {
+                interactions::send_error(ctx, invocation, e.render(&lang)).await?;
+                return Ok(());
             }
-            timer = Duration::from_secs(300);
-        } else if (index == 1 && card_index < length) || forward {
-            forward = false;
-            card_index += 1;
-            message.edit(&ctx.http, |m| {
-                if inventory[card_index].link.is_empty() {
-                    m.content(format!("{}'s inventory: Card {}/{}", msg.author.mention(), card_index + 1, length + 1)).embed(|e| e.title(&inventory[card_index].name).description(&inventory[card_index].set).footer(|f| f.text(format!("{} - ID: {} - Quantity: {}", &inventory[card_index].theme, &inventory[card_index].id, &inventory[card_index].quantity))).image(&inventory[card_index].image))
-                } else {
-                    m.content(format!("{}'s inventory: Card {}/{}", msg.author.mention(), card_index + 1, length + 1)).embed(|e| e.title(&inventory[card_index].name).url(&inventory[card_index].link).description(&inventory[card_index].set).footer(|f| f.text(format!("{} - ID: {} - Quantity: {}", &inventory[card_index].theme, &inventory[card_index].id, &inventory[card_index].quantity))).image(&inventory[card_index].image))
-                }
-            }).await?;
-            message.delete_reactions(ctx).await?;
-            if card_index == length {
-                selection = interactions::reaction_prompt(ctx, &message, &msg.author, &backward_emoji, 30.0).await?;
-                backward = true;
-            } else {
-                selection = interactions::reaction_prompt(ctx, &message, &msg.author, &emojis, 30.0).await?;
+        }
+    };
+    match firebase::set_user_setting(invocation.author().id.to_string(), "default_sort", value).await {
+        Ok(_) => {
+            invocation.reply(ctx, locale::text(&lang, "defaultsort.saved", &[])).await?;
+        },
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[description("A player's collection summary")]
+#[usage("[@user]")]
+#[example("@user")]
+pub async fn profile(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_profile(ctx, msg.into(), args).await
+}
+
+pub async fn run_profile(ctx: &Context, invocation: Invocation<'_>, mut args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let owner = args.single::<String>().ok().and_then(|s| misc::parse_user(&s)).unwrap_or(invocation.author().id);
+    if let Err(e) = check_privacy(ctx, invocation, owner).await {
+        interactions::send_error(ctx, invocation, e).await?;
+        return Ok(());
+    }
+    let profile = match firebase::get_profile(invocation.scope(ctx).await?.key(owner)).await {
+        Ok(Some(profile)) => profile,
+        Ok(None) => {
+            invocation.reply(ctx, locale::text(&lang, "profile.no-rolls", &[("user", &owner.mention())])).await?;
+            return Ok(());
+        },
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+            return Ok(());
+        }
+    };
+
+    let catalog = match catalog::catalog(ctx).await {
+        Ok(catalog) => catalog.iter().filter(|card| !card.retired).cloned().collect::<Vec<_>>(),
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+            return Ok(());
+        }
+    };
+
+    let owned = |card: &firebase::GeneratedCard| profile.cards.iter().find(|(id, _)| *id == card.id).map(|(_, quantity)| *quantity);
+    let mut category_lines = vec![];
+    for category in ["characters", "posters"] {
+        let in_category: Vec<&firebase::GeneratedCard> = catalog.iter().filter(|card| card.category.to_lowercase() == category).collect();
+        let total: u32 = in_category.iter().filter_map(|card| owned(card)).map(u32::from).sum();
+        let unique = in_category.iter().filter(|card| owned(card).is_some()).count();
+        category_lines.push(locale::text(&lang, "profile.category", &[("category", &category), ("total", &total), ("unique", &unique), ("size", &in_category.len())]));
+    }
+
+    let mut sets: Vec<(String, usize, usize)> = vec![];
+    for card in catalog.iter() {
+        let index = match sets.iter().position(|(set, _, _)| *set == card.set) {
+            Some(index) => index,
+            None => {
+                sets.push((card.set.clone(), 0, 0));
+                sets.len() - 1
             }
-            timer = Duration::from_secs(300);
+        };
+        sets[index].2 += 1;
+        if owned(card).is_some() {
+            sets[index].1 += 1;
         }
-    }   
+    }
+    sets.retain(|(_, unique, _)| *unique > 0);
+    sets.sort_by_key(|(set, unique, size)| (std::cmp::Reverse(unique * 100 / size), set.to_lowercase()));
+    let set_lines: Vec<String> = sets.iter().take(config::PROFILESETS)
+        .map(|(set, unique, size)| format!("{}: {}/{} ({}%)", set, unique, size, unique * 100 / size))
+        .collect();
+
+    let showcase = profile.showcase.as_ref().and_then(|id| catalog.iter().find(|card| card.id == *id));
+    let joined = match profile.joined {
+        Some(joined) => format!("<t:{}:D>", joined.timestamp()),
+        None => locale::text(&lang, "unknown", &[]),
+    };
+    let name = owner.to_user(ctx).await.map(|user| user.name).unwrap_or_else(|_| owner.to_string());
+    let mut embed = CreateEmbed::default();
+    embed.title(locale::text(&lang, "profile.title", &[("user", &name)]))
+        .description(category_lines.join("\n"))
+        .field(locale::text(&lang, "profile.joined", &[]), joined, true)
+        .field(locale::text(&lang, "profile.rolls", &[]), profile.roll_count, true);
+    if let Some(balance) = profile.balance {
+        embed.field(locale::text(&lang, "profile.balance", &[]), balance, true);
+    }
+    embed.field(locale::text(&lang, "profile.sets", &[]), if set_lines.is_empty() { locale::text(&lang, "profile.no-sets", &[]) } else { set_lines.join("\n") }, false);
+    if let Some(card) = showcase {
+        embed.image(&card.image).footer(|f| f.text(locale::text(&lang, "profile.showcase", &[("card", &card.name), ("id", &card.id)])));
+    }
+    invocation.send_embed(ctx, "", embed).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Show or choose the card featured on your profile")]
+#[usage("[card ID or name|off]")]
+#[example("100231")]
+#[example("off")]
+pub async fn showcase(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_showcase(ctx, msg.into(), args).await
+}
+
+pub async fn run_showcase(ctx: &Context, invocation: Invocation<'_>, args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let input = args.rest().trim();
+    if input.is_empty() {
+        let current = match firebase::get_user_settings(invocation.player(ctx).await?).await?.showcase {
+            Some(card_id) => locale::text(&lang, "showcase.current-card", &[("id", &card_id)]),
+            None => locale::text(&lang, "showcase.current-favorite", &[]),
+        };
+        invocation.reply(ctx, locale::text(&lang, "showcase.change", &[("current", &current)])).await?;
+        return Ok(());
+    }
+    let (value, reply) = if input == "off" {
+        (json!({ "nullValue": null }), locale::text(&lang, "showcase.off", &[]))
+    } else {
+        let card = match catalog::resolve_card(ctx, input).await {
+            Ok(card) => card,
+            Err(e) => {
+                interactions::send_error(ctx, invocation, e.render(&lang)).await?;
+                return Ok(());
+            }
+        };
+        if firebase::owned_quantity(invocation.player(ctx).await?, card.id.clone()).await? == 0 {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "showcase.not-owned", &[])).await?;
+            return Ok(());
+        }
+        (json!({ "stringValue": card.id }), locale::text(&lang, "showcase.set", &[("card", &card.name)]))
+    };
+    match firebase::set_user_setting(invocation.player(ctx).await?, "showcase", value).await {
+        Ok(_) => {
+            invocation.reply(ctx, reply).await?;
+        },
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
+    }
     Ok(())
 }
 
 #[command]
 #[aliases("t")]
+#[description("Offer a card to another player")]
+#[usage("@user (card ID or name)")]
+#[example("@user 100231")]
 pub async fn trade(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_trade(ctx, msg.into(), args).await
+}
+
+pub async fn run_trade(ctx: &Context, invocation: Invocation<'_>, args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
     let passed_args = args.rest().to_string();
     let mut split_args = passed_args.split_whitespace();
-    let mut user_id = split_args.next().unwrap();
-    let user_id_len = user_id.len();
-    user_id = &user_id[2..user_id_len-1];
-    let card_id = split_args.next().unwrap();
-    let status = firebase::trade_card(msg.author.id.to_string(), card_id.to_string(), user_id.to_string()).await.clone();
-    if status.is_err() {
-        msg.reply(&ctx, format!("Error: {}", status.err().expect("Invalid Error"))).await?;
+    let user = split_args.next().and_then(misc::parse_user);
+    let card_query = split_args.collect::<Vec<&str>>().join(" ");
+    let user = match user {
+        Some(user) if !card_query.is_empty() => user,
+        _ => {
+            invocation.reply(ctx, locale::text(&lang, "trade.usage", &[])).await?;
+            return Ok(());
+        }
+    };
+    if user == invocation.author().id {
+        invocation.reply(ctx, locale::text(&lang, "trade.self", &[])).await?;
         return Ok(());
     }
-    msg.reply(&ctx, format!("Successfully transferred card: {}.", card_id)).await?;
+    if let Err(e) = safeguards::check_trade(ctx, invocation.guild_id(), invocation.author(), user).await {
+        invocation.reply(ctx, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        return Ok(());
+    }
+    let card = match catalog::resolve_card(ctx, &card_query).await {
+        Ok(card) => card,
+        Err(e) => {
+            interactions::send_error(ctx, invocation, e.render(&lang)).await?;
+            return Ok(());
+        }
+    };
+    let status = firebase::create_trade_offer(invocation.player(ctx).await?, card.id, invocation.scope(ctx).await?.key(user)).await;
+    match status {
+        Ok(offer) => {
+            invocation.say(ctx, locale::text(&lang, "trade.offered", &[("user", &user.mention()), ("sender", &invocation.author().mention()), ("card", &offer.card_id), ("offer", &offer.id), ("expires", &offer.expires.timestamp())])).await?;
+        },
+        Err(e) => {
+            invocation.reply(ctx, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
+    }
     Ok(())
 }
 
 #[command]
-#[aliases("h")]
-pub async fn help (ctx: &Context, msg: &Message) -> CommandResult {
-    msg.reply(&ctx, "To roll a card use the command \"!roll (category)\". The current category options are characters or posters. You can view your inventory with \"!inventory (category)\"").await?;
+#[description("Accept a trade offer")]
+#[usage("(offer ID)")]
+pub async fn accept(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_accept(ctx, msg.into(), args).await
+}
+
+pub async fn run_accept(ctx: &Context, invocation: Invocation<'_>, mut args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let offer_id = match args.single::<String>() {
+        Ok(offer_id) => offer_id,
+        Err(_) => {
+            invocation.reply(ctx, locale::text(&lang, "trades.missing-id", &[])).await?;
+            return Ok(());
+        }
+    };
+    match firebase::accept_trade_offer(offer_id.to_lowercase(), invocation.player(ctx).await?).await {
+        Ok(offer) => {
+            invocation.reply(ctx, locale::text(&lang, "accept.done", &[("card", &offer.card_id), ("user", &scope::mention(&offer.from))])).await?;
+            safeguards::flag_transfer_pattern(ctx, invocation.guild_id(), &offer).await;
+        },
+        Err(e) => {
+            invocation.reply(ctx, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+#[command]
+#[description("Decline a trade offer, or cancel one you sent")]
+#[usage("(offer ID)")]
+pub async fn decline(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_decline(ctx, msg.into(), args).await
+}
+
+pub async fn run_decline(ctx: &Context, invocation: Invocation<'_>, mut args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let offer_id = match args.single::<String>() {
+        Ok(offer_id) => offer_id,
+        Err(_) => {
+            invocation.reply(ctx, locale::text(&lang, "trades.missing-id", &[])).await?;
+            return Ok(());
+        }
+    };
+    match firebase::cancel_trade_offer(offer_id.to_lowercase(), invocation.player(ctx).await?).await {
+        Ok(offer) => {
+            let key = if offer.from == invocation.player(ctx).await? { "decline.cancelled" } else { "decline.declined" };
+            invocation.reply(ctx, locale::text(&lang, key, &[("offer", &offer.id), ("card", &offer.card_id)])).await?;
+        },
+        Err(e) => {
+            invocation.reply(ctx, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[description("Take back your latest trade")]
+pub async fn undo(ctx: &Context, msg: &Message) -> CommandResult {
+    run_undo(ctx, msg.into()).await
+}
+
+pub async fn run_undo(ctx: &Context, invocation: Invocation<'_>) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    match firebase::undo_trade(invocation.player(ctx).await?).await {
+        Ok(entry) => {
+            invocation.reply(ctx, locale::text(&lang, "undo.done", &[("card", &entry.card_id), ("user", &scope::mention(&entry.counterparty.unwrap_or_default()))])).await?;
+        },
+        Err(e) => {
+            invocation.reply(ctx, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[description("Your pending trade offers")]
+#[usage("[cancel (offer ID)]")]
+pub async fn trades(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_trades(ctx, msg.into(), args).await
+}
+
+pub async fn run_trades(ctx: &Context, invocation: Invocation<'_>, args: Args) -> CommandResult {
+    let passed_args = args.rest().to_string();
+    let mut split_args = passed_args.split_whitespace();
+    if let Some("cancel") = split_args.next() {
+        return run_decline(ctx, invocation, Args::new(split_args.next().unwrap_or_default(), &[])).await;
+    }
+    let lang = invocation.locale(ctx).await;
+    let offers = match firebase::pending_trade_offers(invocation.player(ctx).await?).await {
+        Ok(offers) => offers,
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+            return Ok(());
+        }
+    };
+    if offers.is_empty() {
+        invocation.reply(ctx, locale::text(&lang, "trades.none", &[])).await?;
+        return Ok(());
+    }
+    let author_id = invocation.player(ctx).await?;
+    let lines: Vec<String> = offers.iter().map(|offer| {
+        let (key, user) = if offer.from == author_id { ("trades.to", scope::mention(&offer.to)) } else { ("trades.from", scope::mention(&offer.from)) };
+        locale::text(&lang, key, &[("offer", &offer.id), ("card", &offer.card_id), ("user", &user), ("expires", &offer.expires.timestamp())])
+    }).collect();
+    let mut embed = CreateEmbed::default();
+    embed.description(lines.join("\n")).footer(|f| f.text(locale::text(&lang, "trades.footer", &[])));
+    invocation.send_embed(ctx, locale::text(&lang, "trades.title", &[("user", &invocation.author().mention())]), embed).await?;
+    Ok(())
+}
+
+async fn set_flag(ctx: &Context, invocation: Invocation<'_>, args: Args, flag: firebase::CardFlag, value: bool, done: &str) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let card = match catalog::resolve_card(ctx, args.rest()).await {
+        Ok(card) => card,
+        Err(e) => {
+            interactions::send_error(ctx, invocation, e.render(&lang)).await?;
+            return Ok(());
+        }
+    };
+    match firebase::set_card_flag(invocation.player(ctx).await?, card.id.clone(), flag, value).await {
+        Ok(_) => {
+            invocation.reply(ctx, locale::text(&lang, done, &[("id", &card.id), ("card", &card.name)])).await?;
+        },
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[description("Protect a card from being traded")]
+#[usage("(card ID or name)")]
+#[example("100231")]
+pub async fn lock(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_lock(ctx, msg.into(), args).await
+}
+
+pub async fn run_lock(ctx: &Context, invocation: Invocation<'_>, args: Args) -> CommandResult {
+    set_flag(ctx, invocation, args, firebase::CardFlag::Locked, true, "flag.locked").await
+}
+
+#[command]
+#[description("Allow a locked card to be traded again")]
+#[usage("(card ID or name)")]
+#[example("100231")]
+pub async fn unlock(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_unlock(ctx, msg.into(), args).await
+}
+
+pub async fn run_unlock(ctx: &Context, invocation: Invocation<'_>, args: Args) -> CommandResult {
+    set_flag(ctx, invocation, args, firebase::CardFlag::Locked, false, "flag.unlocked").await
+}
+
+#[command]
+#[aliases("fav")]
+#[description("Mark a card as a favorite")]
+#[usage("(card ID or name)")]
+#[example("100231")]
+pub async fn favorite(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_favorite(ctx, msg.into(), args).await
+}
+
+pub async fn run_favorite(ctx: &Context, invocation: Invocation<'_>, args: Args) -> CommandResult {
+    set_flag(ctx, invocation, args, firebase::CardFlag::Favorite, true, "flag.favorited").await
+}
+
+#[command]
+#[aliases("unfav")]
+#[description("Remove a card from your favorites")]
+#[usage("(card ID or name)")]
+#[example("100231")]
+pub async fn unfavorite(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_unfavorite(ctx, msg.into(), args).await
+}
+
+pub async fn run_unfavorite(ctx: &Context, invocation: Invocation<'_>, args: Args) -> CommandResult {
+    set_flag(ctx, invocation, args, firebase::CardFlag::Favorite, false, "flag.unfavorited").await
+}
+
+#[command]
+#[aliases("cd")]
+#[description("When you can roll, look at your inventory and trade again")]
+pub async fn cooldowns(ctx: &Context, msg: &Message) -> CommandResult {
+    run_cooldowns(ctx, msg.into()).await
+}
+
+pub async fn run_cooldowns(ctx: &Context, invocation: Invocation<'_>) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let cooldowns = match firebase::get_cooldowns(invocation.player(ctx).await?).await {
+        Ok(cooldowns) => cooldowns,
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+            return Ok(());
+        }
+    };
+    let (sent, trade_reset) = safeguards::trade_cap_status(invocation.player(ctx).await?).await?;
+    let status = |ready: Option<DateTime<Utc>>| match ready {
+        Some(ready) => format!("<t:{}:R>", ready.timestamp()),
+        None => locale::text(&lang, "cooldowns.ready", &[]),
+    };
+    let mut embed = CreateEmbed::default();
+    embed.field(locale::text(&lang, "cooldowns.roll", &[]), status(cooldowns.roll), true)
+        .field(locale::text(&lang, "cooldowns.inventory", &[]), status(cooldowns.inventory), true)
+        .field(locale::text(&lang, "cooldowns.trades", &[("sent", &sent.min(config::DAILYTRADECAP)), ("cap", &config::DAILYTRADECAP)]), status(trade_reset), true);
+    invocation.send_embed(ctx, locale::text(&lang, "cooldowns.title", &[("user", &invocation.author().mention())]), embed).await?;
+    Ok(())
+}
+
+#[command]
+#[description("Get a message when your next roll is ready")]
+#[usage("[on [#channel]|off]")]
+#[example("on")]
+#[example("on #bot-spam")]
+#[example("off")]
+pub async fn remind(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_remind(ctx, msg.into(), args).await
+}
+
+pub async fn run_remind(ctx: &Context, invocation: Invocation<'_>, mut args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let user_id = invocation.player(ctx).await?;
+    let enabled = match args.single::<String>().map(|s| s.to_lowercase()).as_deref() {
+        Ok("on") => true,
+        Ok("off") => false,
+        _ => {
+            let settings = firebase::get_user_settings(user_id).await?;
+            let current = match (settings.remind, settings.remind_channel) {
+                (false, _) => locale::text(&lang, "remind.status-off", &[]),
+                (true, None) => locale::text(&lang, "remind.status-dm", &[]),
+                (true, Some(channel)) => locale::text(&lang, "remind.status-channel", &[("channel", &channel)]),
+            };
+            invocation.reply(ctx, locale::text(&lang, "remind.current", &[("status", &current)])).await?;
+            return Ok(());
+        }
+    };
+    let channel_id = if enabled && !args.is_empty() {
+        let channel = match serenity::utils::parse_channel(args.rest().trim()) {
+            Some(channel) => ChannelId(channel),
+            None => {
+                invocation.reply(ctx, locale::text(&lang, "remind.usage", &[])).await?;
+                return Ok(());
+            }
+        };
+        let in_guild = match channel.to_channel(ctx).await {
+            Ok(Channel::Guild(channel)) => Some(channel.guild_id) == invocation.guild_id(),
+            _ => false,
+        };
+        if !in_guild {
+            invocation.reply(ctx, locale::text(&lang, "remind.other-guild", &[])).await?;
+            return Ok(());
+        }
+        Some(channel.0)
+    } else {
+        None
+    };
+    match firebase::set_reminders(user_id, enabled, channel_id).await {
+        Ok(_) => {
+            let reply = match (enabled, channel_id) {
+                (false, _) => locale::text(&lang, "remind.disabled", &[]),
+                (true, None) => locale::text(&lang, "remind.enabled-dm", &[]),
+                (true, Some(channel)) => locale::text(&lang, "remind.enabled-channel", &[("channel", &channel)]),
+            };
+            invocation.reply(ctx, reply).await?;
+        },
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[aliases("lang")]
+#[description("Show or choose the language the bot answers you in")]
+#[usage("[language code|reset]")]
+#[example("es")]
+#[example("reset")]
+pub async fn language(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_language(ctx, msg.into(), args).await
+}
+
+pub async fn run_language(ctx: &Context, invocation: Invocation<'_>, mut args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let new_locale = match args.single::<String>().map(|s| s.to_lowercase()) {
+        Ok(code) if code == "reset" => None,
+        Ok(code) if locale::supported().contains(&code.as_str()) => Some(code),
+        Ok(code) => {
+            invocation.reply(ctx, locale::text(&lang, "language.unknown", &[("code", &code), ("available", &locale::available())])).await?;
+            return Ok(());
+        },
+        Err(_) => {
+            invocation.reply(ctx, locale::text(&lang, "language.current", &[("language", &locale::describe(&lang)), ("available", &locale::available())])).await?;
+            return Ok(());
+        }
+    };
+    let reset = new_locale.is_none();
+    match locale::set_user_locale(ctx, invocation.author().id, new_locale).await {
+        Ok(_) => {
+            let lang = invocation.locale(ctx).await;
+            let key = if reset { "language.reset" } else { "language.set" };
+            invocation.reply(ctx, locale::text(&lang, key, &[("language", &locale::describe(&lang))])).await?;
+        },
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[description("Your recent card movements")]
+pub async fn history(ctx: &Context, msg: &Message) -> CommandResult {
+    run_history(ctx, msg.into()).await
+}
+
+pub async fn run_history(ctx: &Context, invocation: Invocation<'_>) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let entries = match firebase::ledger_for_user(invocation.player(ctx).await?).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            interactions::send_error(ctx, invocation, locale::text(&lang, "error", &[("error", &e.render(&lang))])).await?;
+            return Ok(());
+        }
+    };
+    if entries.is_empty() {
+        invocation.reply(ctx, locale::text(&lang, "history.none", &[])).await?;
+        return Ok(());
+    }
+    let lines: Vec<String> = entries.iter().take(config::HISTORYLENGTH).map(|entry| misc::describe_ledger_entry(&lang, entry)).collect();
+    let mut embed = CreateEmbed::default();
+    embed.description(lines.join("\n")).footer(|f| f.text(locale::text(&lang, "history.footer", &[("shown", &lines.len()), ("total", &entries.len())])));
+    invocation.send_embed(ctx, locale::text(&lang, "history.title", &[("user", &invocation.author().mention())]), embed).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::firebase::GeneratedCard;
+
+    fn inventory(count: usize) -> Vec<GeneratedCard> {
+        (0..count).map(|i| GeneratedCard { set: "Marvel".to_string(), ..GeneratedCard::sample(&i.to_string(), &format!("Card {}", i)) }).collect()
+    }
+
+    #[test]
+    fn list_pages_hold_listpagesize_cards() {
+        assert_eq!(inventory_page_count(0, true), 0);
+        assert_eq!(inventory_page_count(config::LISTPAGESIZE, true), 1);
+        assert_eq!(inventory_page_count(config::LISTPAGESIZE + 1, true), 2);
+        assert_eq!(inventory_page_count(config::LISTPAGESIZE + 1, false), config::LISTPAGESIZE + 1);
+    }
+
+    #[test]
+    fn list_rows_slice_the_requested_page() {
+        let cards = inventory(config::LISTPAGESIZE + 3);
+        let first = list_rows(&cards, 0);
+        assert_eq!(first.len(), config::LISTPAGESIZE);
+        assert!(first[0].starts_with("`0` **Card 0**"));
+        let last = list_rows(&cards, 1);
+        assert_eq!(last.len(), 3);
+        assert!(last[0].starts_with(&format!("`{id}` **Card {id}**", id = config::LISTPAGESIZE)));
+        assert!(list_rows(&cards, 2).is_empty());
+    }
+
+    #[test]
+    fn list_rows_show_quantity_and_markers() {
+        let mut card = GeneratedCard { set: "DC".to_string(), quantity: 3, ..GeneratedCard::sample("100003", "Batman") };
+        assert_eq!(list_rows(std::slice::from_ref(&card), 0), vec!["`100003` **Batman** - DC x3"]);
+        card.favorite = true;
+        card.locked = true;
+        assert_eq!(list_rows(&[card], 0), vec!["`100003` **Batman** - DC x3 ⭐ 🔒"]);
+    }
+}
@@ -0,0 +1,128 @@
+use serenity::builder::CreateEmbed;
+use serenity::framework::standard::macros::command;
+use serenity::framework::standard::{Command, CommandGroup, CommandResult, Args};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use crate::config;
+use crate::interactions::Invocation;
+use crate::locale;
+use crate::permissions::{self, Tier};
+use crate::{ADMIN_GROUP, BOT_GROUP, GAMEPLAY_GROUP, GENERAL_GROUP, MODERATION_GROUP, OWNER_GROUP, ROLLING_GROUP};
+
+const GROUPS: [&CommandGroup; 7] = [&ROLLING_GROUP, &GAMEPLAY_GROUP, &GENERAL_GROUP, &MODERATION_GROUP, &ADMIN_GROUP, &OWNER_GROUP, &BOT_GROUP];
+
+/// How often a command can be used, for the commands that are limited.
+fn cooldown(lang: &str, name: &str) -> Option<String> {
+    match name {
+        "roll" => Some(locale::text(lang, "help.cooldown.minutes", &[("minutes", &config::ROLLTIME)])),
+        "inventory" => Some(locale::text(lang, "help.cooldown.minutes", &[("minutes", &config::INVTIME)])),
+        "trade" => Some(locale::text(lang, "help.cooldown.trade", &[("cap", &config::DAILYTRADECAP), ("minutes", &config::TRADEEXPIRY)])),
+        "undo" => Some(locale::text(lang, "help.cooldown.undo", &[("seconds", &config::UNDOTIME)])),
+        _ => None,
+    }
+}
+
+/// Who can use a group that needs a permission tier.
+fn audience(lang: &str, tier: Tier) -> String {
+    locale::text(lang, &format!("help.audience.{}", tier.as_str()), &[])
+}
+
+/// What a command does, from the catalog when it has been written there and from the
+/// command's description otherwise.
+fn description(lang: &str, command: &Command) -> String {
+    locale::lookup(lang, &format!("help.command.{}", command.options.names[0]))
+        .cloned()
+        .unwrap_or_else(|| command.options.desc.unwrap_or_default().to_string())
+}
+
+fn find_command(prefix: &str, name: &str) -> Option<(&'static CommandGroup, &'static Command)> {
+    let name = name.trim_start_matches(prefix).to_lowercase();
+    GROUPS.iter().find_map(|group| {
+        group.options.commands.iter()
+            .find(|command| command.options.names.contains(&name.as_str()))
+            .map(|command| (*group, *command))
+    })
+}
+
+fn overview(lang: &str, prefix: &str) -> CreateEmbed {
+    let mut sections = vec![];
+    for group in GROUPS {
+        let mut lines = vec![];
+        let name = locale::text(lang, &format!("help.group.{}", group.name.to_lowercase()), &[]);
+        match permissions::group_tier(group) {
+            Some(tier) => lines.push(format!("**{}** ({})", name, audience(lang, tier).to_lowercase())),
+            None => lines.push(format!("**{}**", name)),
+        }
+        for command in group.options.commands.iter().filter(|command| command.options.help_available) {
+            let names = command.options.names;
+            let aliases = if names.len() > 1 { format!(" ({})", names[1..].join(", ")) } else { String::new() };
+            lines.push(format!("`{}{}`{} - {}", prefix, names[0], aliases, description(lang, command)));
+        }
+        sections.push(lines.join("\n"));
+    }
+    let mut embed = CreateEmbed::default();
+    embed.title(locale::text(lang, "help.title", &[]))
+        .description(sections.join("\n\n"))
+        .footer(|f| f.text(locale::text(lang, "help.footer", &[("prefix", &prefix)])));
+    embed
+}
+
+fn details(lang: &str, prefix: &str, group: &CommandGroup, command: &Command) -> CreateEmbed {
+    let options = command.options;
+    let name = options.names[0];
+    let mut embed = CreateEmbed::default();
+    embed.title(format!("{}{}", prefix, name))
+        .description(description(lang, command))
+        .field(locale::text(lang, "help.usage", &[]), match options.usage {
+            Some(usage) => format!("`{}{} {}`", prefix, name, usage),
+            None => format!("`{}{}`", prefix, name),
+        }, false);
+    if !options.examples.is_empty() {
+        let examples: Vec<String> = options.examples.iter().map(|example| format!("`{}{} {}`", prefix, name, example)).collect();
+        embed.field(locale::text(lang, "help.examples", &[]), examples.join("\n"), false);
+    }
+    if options.names.len() > 1 {
+        let aliases: Vec<String> = options.names[1..].iter().map(|alias| format!("`{}{}`", prefix, alias)).collect();
+        embed.field(locale::text(lang, "help.aliases", &[]), aliases.join(", "), true);
+    }
+    if let Some(cooldown) = cooldown(lang, name) {
+        embed.field(locale::text(lang, "help.cooldown", &[]), cooldown, true);
+    }
+    if let Some(tier) = permissions::group_tier(group) {
+        embed.field(locale::text(lang, "help.permissions", &[]), audience(lang, tier), true);
+    }
+    embed
+}
+
+#[command]
+#[aliases("h")]
+#[description("List every command, or explain one of them")]
+#[usage("[command]")]
+#[example("roll")]
+#[example("t")]
+pub async fn help(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    run_help(ctx, msg.into(), args).await
+}
+
+pub async fn run_help(ctx: &Context, invocation: Invocation<'_>, args: Args) -> CommandResult {
+    let lang = invocation.locale(ctx).await;
+    let prefix = match invocation.guild_id() {
+        Some(guild_id) => crate::prefix::guild_prefix(ctx, guild_id).await,
+        None => config::DEFAULTPREFIX.to_string(),
+    };
+    let query = args.rest().trim();
+    if query.is_empty() {
+        invocation.send_embed(ctx, "", overview(&lang, &prefix)).await?;
+        return Ok(());
+    }
+    match find_command(&prefix, query) {
+        Some((group, command)) => {
+            invocation.send_embed(ctx, "", details(&lang, &prefix, group, command)).await?;
+        },
+        None => {
+            invocation.reply(ctx, locale::text(&lang, "help.unknown", &[("command", &query), ("prefix", &prefix)])).await?;
+        }
+    }
+    Ok(())
+}
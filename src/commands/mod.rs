@@ -1 +1,4 @@
-pub mod cards;
\ No newline at end of file
+pub mod admin;
+pub mod cards;
+pub mod help;
+pub mod slash;
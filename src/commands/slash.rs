@@ -0,0 +1,414 @@
+use serde_json::Value;
+use serenity::builder::{CreateApplicationCommand, CreateApplicationCommands};
+use serenity::framework::standard::{Args, Delimiter};
+use serenity::model::interactions::application_command::{ApplicationCommandInteraction, ApplicationCommandOptionType};
+use serenity::model::interactions::autocomplete::AutocompleteInteraction;
+use serenity::prelude::*;
+use tracing::error;
+
+use crate::allowlist;
+use crate::catalog;
+use crate::commands::admin::*;
+use crate::commands::cards::*;
+use crate::commands::help::*;
+use crate::config;
+use crate::filters;
+use crate::interactions::Invocation;
+use crate::locale;
+use crate::misc;
+use crate::permissions;
+
+fn option(command: &mut CreateApplicationCommand, name: &str, description: &str, kind: ApplicationCommandOptionType, required: bool, choices: &[&str]) {
+    command.create_option(|o| {
+        o.name(name).description(description).kind(kind).required(required);
+        for choice in choices {
+            o.add_string_choice(choice, choice);
+        }
+        o
+    });
+}
+
+fn category_option(command: &mut CreateApplicationCommand, required: bool) {
+    option(command, "category", "Card category", ApplicationCommandOptionType::String, required, &["characters", "posters"]);
+}
+
+/// A card argument, autocompleted from the catalog by name, set or theme.
+fn card_option(command: &mut CreateApplicationCommand, description: &str, required: bool) {
+    command.create_option(|o| {
+        o.name("card").description(description).kind(ApplicationCommandOptionType::String).required(required).set_autocomplete(true)
+    });
+}
+
+fn user_option(command: &mut CreateApplicationCommand, description: &str, required: bool) {
+    option(command, "user", description, ApplicationCommandOptionType::User, required, &[]);
+}
+
+/// A command for one of the server permission tiers. Discord shows it to everyone in a server,
+/// and the tier is checked when it runs since tiers come from the roles each server maps.
+fn staff(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command.dm_permission(false)
+}
+
+/// A command for the bot's owners, who may also use it in direct messages like its prefix
+/// form. The tier is checked when it runs.
+fn bot_owner(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command.dm_permission(true)
+}
+
+/// Describes every slash command. The options mirror the arguments of the prefix commands.
+pub fn register(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    commands
+        .create_application_command(|c| {
+            category_option(c.name("roll").description("Roll a new card"), true);
+            c
+        })
+        .create_application_command(|c| {
+            c.name("inventory").description("Browse a card collection");
+            category_option(c, true);
+            user_option(c, "Whose collection to browse, yours by default", false);
+            option(c, "set", "Only cards whose set contains this", ApplicationCommandOptionType::String, false, &[]);
+            option(c, "theme", "Only cards whose theme contains this", ApplicationCommandOptionType::String, false, &[]);
+            option(c, "name", "Only cards whose name contains this", ApplicationCommandOptionType::String, false, &[]);
+            option(c, "dupes", "Only cards with more than one copy", ApplicationCommandOptionType::Boolean, false, &[]);
+            option(c, "favorites", "Only favorite cards", ApplicationCommandOptionType::Boolean, false, &[]);
+            option(c, "locked", "Only locked cards", ApplicationCommandOptionType::Boolean, false, &[]);
+            option(c, "sort", "Card order", ApplicationCommandOptionType::String, false, &["name", "set", "theme", "quantity", "recent", "rarity"]);
+            option(c, "order", "Sort direction", ApplicationCommandOptionType::String, false, &["asc", "desc"]);
+            option(c, "layout", "One card at a time, a list or a picture grid", ApplicationCommandOptionType::String, false, &["view", "list", "grid"]);
+            option(c, "page", "Page to open", ApplicationCommandOptionType::Integer, false, &[]);
+            c
+        })
+        .create_application_command(|c| {
+            card_option(c.name("view").description("Look at any card"), "Card ID or name", true);
+            c
+        })
+        .create_application_command(|c| {
+            card_option(c.name("cardstats").description("Circulation statistics for a card"), "Card ID or name", true);
+            c
+        })
+        .create_application_command(|c| {
+            c.name("compare").description("Compare your collection with another player's");
+            user_option(c, "Player to compare with", true);
+            category_option(c, false);
+            c
+        })
+        .create_application_command(|c| {
+            user_option(c.name("profile").description("Show a player's profile"), "Whose profile to show, yours by default", false);
+            c
+        })
+        .create_application_command(|c| {
+            card_option(c.name("showcase").description("Choose the card shown on your profile"), "Card ID or name, or off to show your first favorite", false);
+            c
+        })
+        .create_application_command(|c| {
+            option(c.name("privacy").description("Choose who can see your collection"), "level", "Who can see your collection", ApplicationCommandOptionType::String, false, &["public", "guild", "private"]);
+            c
+        })
+        .create_application_command(|c| {
+            c.name("defaultsort").description("Save your preferred inventory order");
+            option(c, "sort", "Card order, or off to clear it", ApplicationCommandOptionType::String, true, &["name", "set", "theme", "quantity", "recent", "rarity", "off"]);
+            option(c, "order", "Sort direction", ApplicationCommandOptionType::String, false, &["asc", "desc"]);
+            c
+        })
+        .create_application_command(|c| {
+            c.name("trade").description("Offer a card to another player");
+            user_option(c, "Player to offer the card to", true);
+            card_option(c, "Card ID or name", true);
+            c
+        })
+        .create_application_command(|c| {
+            option(c.name("accept").description("Accept a trade offer"), "offer", "Trade offer ID", ApplicationCommandOptionType::String, true, &[]);
+            c
+        })
+        .create_application_command(|c| {
+            option(c.name("decline").description("Decline or cancel a trade offer"), "offer", "Trade offer ID", ApplicationCommandOptionType::String, true, &[]);
+            c
+        })
+        .create_application_command(|c| c.name("undo").description("Take back your latest trade"))
+        .create_application_command(|c| {
+            option(c.name("trades").description("List your pending trade offers"), "cancel", "Trade offer ID to cancel", ApplicationCommandOptionType::String, false, &[]);
+            c
+        })
+        .create_application_command(|c| {
+            card_option(c.name("lock").description("Protect a card from being traded"), "Card ID or name", true);
+            c
+        })
+        .create_application_command(|c| {
+            card_option(c.name("unlock").description("Allow a locked card to be traded again"), "Card ID or name", true);
+            c
+        })
+        .create_application_command(|c| {
+            card_option(c.name("favorite").description("Mark a card as a favorite"), "Card ID or name", true);
+            c
+        })
+        .create_application_command(|c| {
+            card_option(c.name("unfavorite").description("Remove a card from your favorites"), "Card ID or name", true);
+            c
+        })
+        .create_application_command(|c| c.name("cooldowns").description("When you can roll, look at your inventory and trade again"))
+        .create_application_command(|c| {
+            c.name("remind").description("Get a message when your next roll is ready");
+            option(c, "reminders", "Turn reminders on or off, leave empty to see the current setting", ApplicationCommandOptionType::String, false, &["on", "off"]);
+            option(c, "channel", "Ping you in this channel instead of a DM", ApplicationCommandOptionType::Channel, false, &[]);
+            c
+        })
+        .create_application_command(|c| {
+            c.name("language").description("Show or choose the language the bot answers you in");
+            option(c, "language", "Language code, or reset to use the language of the server", ApplicationCommandOptionType::String, false, &[locale::supported(), vec!["reset"]].concat());
+            c
+        })
+        .create_application_command(|c| c.name("history").description("Your recent card movements"))
+        .create_application_command(|c| {
+            c.name("help").description("List every command, or explain one of them");
+            option(c, "command", "Command to explain", ApplicationCommandOptionType::String, false, &[]);
+            c
+        })
+        .create_application_command(|c| {
+            staff(c.name("grant").description("Give a card to a player"));
+            user_option(c, "Player to give the card to", true);
+            card_option(c, "Card ID or name", true);
+            c
+        })
+        .create_application_command(|c| {
+            staff(c.name("revoke").description("Take a card from a player"));
+            user_option(c, "Player to take the card from", true);
+            card_option(c, "Card ID or name", true);
+            c
+        })
+        .create_application_command(|c| {
+            staff(c.name("audit").description("Look up the ledger of a player or a card"));
+            user_option(c, "Player to audit", false);
+            card_option(c, "Card ID or name to audit", false);
+            c
+        })
+        .create_application_command(|c| {
+            staff(c.name("reverse").description("Reverse a recorded card movement"));
+            option(c, "entry", "Ledger ID", ApplicationCommandOptionType::String, true, &[]);
+            c
+        })
+        .create_application_command(|c| bot_owner(c.name("rebuildstats").description("Rebuild the circulation counters")))
+        .create_application_command(|c| {
+            staff(c.name("modlog").description("Choose the channel for moderator reports"));
+            option(c, "channel", "Channel for reports, leave empty to turn reports off", ApplicationCommandOptionType::Channel, false, &[]);
+            c
+        })
+        .create_application_command(|c| {
+            staff(c.name("prefix").description("Show or change the command prefix of this server"));
+            option(c, "prefix", "New prefix, or reset to go back to the default", ApplicationCommandOptionType::String, false, &[]);
+            c
+        })
+        .create_application_command(|c| {
+            staff(c.name("serverlanguage").description("Show or change the language of this server"));
+            option(c, "language", "Language code, or reset to go back to the default", ApplicationCommandOptionType::String, false, &[locale::supported(), vec!["reset"]].concat());
+            c
+        })
+        .create_application_command(|c| {
+            staff(c.name("economy").description("Choose whether this server shares the global collections or keeps its own"));
+            option(c, "mode", "Share the global collections, or keep this server's own", ApplicationCommandOptionType::String, false, &["global", "isolated"]);
+            c
+        })
+        .create_application_command(|c| {
+            staff(c.name("channels").description("Limit rolling or gameplay commands to some channels"));
+            option(c, "group", "Commands to limit", ApplicationCommandOptionType::String, false, &["rolling", "gameplay"]);
+            option(c, "action", "Allow a channel, stop allowing one, or allow every channel again", ApplicationCommandOptionType::String, false, &["add", "remove", "clear"]);
+            option(c, "channel", "Channel to allow or stop allowing", ApplicationCommandOptionType::Channel, false, &[]);
+            c
+        })
+        .create_application_command(|c| {
+            staff(c.name("roles").description("Choose which roles grant the Moderator, Admin and Owner tiers"));
+            option(c, "tier", "Permission tier", ApplicationCommandOptionType::String, false, &["moderator", "admin", "owner"]);
+            option(c, "action", "Map a role, stop mapping one, or remove every role from the tier", ApplicationCommandOptionType::String, false, &["add", "remove", "clear"]);
+            option(c, "role", "Role to map or stop mapping", ApplicationCommandOptionType::Role, false, &[]);
+            c
+        })
+        .create_application_command(|c| {
+            bot_owner(c.name("catalog").description("Add cards to the catalog, remove them, or reload it"));
+            option(c, "action", "What to do with the catalog", ApplicationCommandOptionType::String, true, &["add", "remove", "refresh"]);
+            option(c, "id", "Card ID", ApplicationCommandOptionType::String, false, &[]);
+            category_option(c, false);
+            option(c, "image", "Image URL of a new card", ApplicationCommandOptionType::String, false, &[]);
+            option(c, "name", "Name of a new card", ApplicationCommandOptionType::String, false, &[]);
+            option(c, "set", "Set of a new card", ApplicationCommandOptionType::String, false, &[]);
+            option(c, "theme", "Theme of a new card", ApplicationCommandOptionType::String, false, &[]);
+            option(c, "rarity", "Rarity of a new card", ApplicationCommandOptionType::String, false, &config::RARITIES);
+            option(c, "link", "Link shown on a new card", ApplicationCommandOptionType::String, false, &[]);
+            c
+        })
+}
+
+/// The value of an option written the way the prefix command expects it.
+fn argument(command: &ApplicationCommandInteraction, name: &str) -> Option<String> {
+    let option = command.data.options.iter().find(|option| option.name == name)?;
+    let value = option.value.as_ref()?;
+    Some(match option.kind {
+        ApplicationCommandOptionType::User => format!("<@{}>", value.as_str()?),
+        ApplicationCommandOptionType::Channel => format!("<#{}>", value.as_str()?),
+        ApplicationCommandOptionType::Role => format!("<@&{}>", value.as_str()?),
+        _ => match value {
+            Value::String(value) => value.clone(),
+            value => value.to_string(),
+        },
+    })
+}
+
+fn arguments(command: &ApplicationCommandInteraction, names: &[&str]) -> String {
+    names.iter().filter_map(|name| argument(command, name)).collect::<Vec<String>>().join(" ")
+}
+
+/// The inventory the options ask for. The options are already split into fields, so they are
+/// read directly rather than written back into prefix arguments.
+fn inventory_request(command: &ApplicationCommandInteraction) -> InventoryRequest {
+    let text = |name: &str| argument(command, name).map(|value| value.to_lowercase());
+    let flag = |name: &str| argument(command, name).as_deref() == Some("true");
+    let filter = filters::CardFilter {
+        set: text("set"),
+        theme: text("theme"),
+        name: text("name"),
+        dupes: flag("dupes"),
+        favorites: flag("favorites"),
+        locked: flag("locked"),
+    };
+    let sort = text("sort").and_then(|key| filters::SortKey::from_name(&key)).map(|key| {
+        let mut sort = filters::CardSort::new(key);
+        match text("order").as_deref() {
+            Some("asc") => sort.descending = false,
+            Some("desc") => sort.descending = true,
+            _ => {},
+        }
+        sort
+    });
+    InventoryRequest {
+        category: text("category").unwrap_or_default(),
+        owner: argument(command, "user").and_then(|user| misc::parse_user(&user)).unwrap_or(command.user.id),
+        filter,
+        sort,
+        layout: text("layout").and_then(|layout| Layout::from_name(&layout)).unwrap_or(Layout::View),
+        start_page: text("page").and_then(|page| page.parse::<usize>().ok()).unwrap_or(1).saturating_sub(1),
+    }
+}
+
+/// The catalog change the options ask for, or the message explaining what is missing.
+fn catalog_action(command: &ApplicationCommandInteraction, lang: &str) -> Result<CatalogAction, String> {
+    let usage = || locale::text(lang, "catalog.usage", &[]);
+    match argument(command, "action").as_deref() {
+        Some("add") => {
+            let (category, id, image) = match (argument(command, "category"), argument(command, "id"), argument(command, "image")) {
+                (Some(category), Some(id), Some(image)) => (category, id, image),
+                _ => return Err(usage()),
+            };
+            let values: Vec<(&str, String)> = ["name", "set", "theme", "rarity", "link"].into_iter()
+                .filter_map(|field| argument(command, field).map(|value| (field, value)))
+                .collect();
+            let fields: Vec<(&str, &str)> = values.iter().map(|(field, value)| (*field, value.as_str())).collect();
+            catalog_card(lang, &category, &id, &image, &fields).map(CatalogAction::Add)
+        },
+        Some("remove") => argument(command, "id").map(CatalogAction::Remove).ok_or_else(usage),
+        Some("refresh") => Ok(CatalogAction::Refresh),
+        _ => Err(usage()),
+    }
+}
+
+/// Runs a slash command through the same code as its prefix version.
+pub async fn handle(ctx: &Context, command: &ApplicationCommandInteraction) {
+    if let Err(why) = command.defer(&ctx.http).await {
+        error!("Could not defer slash command {}: {:?}", command.data.name, why);
+        return;
+    }
+    let invocation = Invocation::Slash(command);
+    let name = command.data.name.as_str();
+
+    if let Some(tier) = permissions::required_tier(name) {
+        if let Err(reason) = permissions::require(ctx, invocation, name, tier).await {
+            let _ = invocation.reply(ctx, reason).await;
+            return;
+        }
+    }
+    if !allowlist::check(ctx, invocation, name).await {
+        return;
+    }
+
+    let args = |input: String| Args::new(&input, &[Delimiter::Single(' ')]);
+    let result = match name {
+        "roll" => run_roll(ctx, invocation, args(arguments(command, &["category"]))).await,
+        "inventory" => show_inventory(ctx, invocation, inventory_request(command)).await,
+        "view" => run_view(ctx, invocation, args(arguments(command, &["card"]))).await,
+        "cardstats" => run_cardstats(ctx, invocation, args(arguments(command, &["card"]))).await,
+        "compare" => run_compare(ctx, invocation, args(arguments(command, &["user", "category"]))).await,
+        "profile" => run_profile(ctx, invocation, args(arguments(command, &["user"]))).await,
+        "showcase" => run_showcase(ctx, invocation, args(arguments(command, &["card"]))).await,
+        "privacy" => run_privacy(ctx, invocation, args(arguments(command, &["level"]))).await,
+        "defaultsort" => run_defaultsort(ctx, invocation, args(arguments(command, &["sort", "order"]))).await,
+        "trade" => run_trade(ctx, invocation, args(arguments(command, &["user", "card"]))).await,
+        "accept" => run_accept(ctx, invocation, args(arguments(command, &["offer"]))).await,
+        "decline" => run_decline(ctx, invocation, args(arguments(command, &["offer"]))).await,
+        "undo" => run_undo(ctx, invocation).await,
+        "trades" => match argument(command, "cancel") {
+            Some(offer_id) => run_trades(ctx, invocation, args(format!("cancel {}", offer_id))).await,
+            None => run_trades(ctx, invocation, args(String::new())).await,
+        },
+        "lock" => run_lock(ctx, invocation, args(arguments(command, &["card"]))).await,
+        "unlock" => run_unlock(ctx, invocation, args(arguments(command, &["card"]))).await,
+        "favorite" => run_favorite(ctx, invocation, args(arguments(command, &["card"]))).await,
+        "unfavorite" => run_unfavorite(ctx, invocation, args(arguments(command, &["card"]))).await,
+        "cooldowns" => run_cooldowns(ctx, invocation).await,
+        "remind" => run_remind(ctx, invocation, args(arguments(command, &["reminders", "channel"]))).await,
+        "language" => run_language(ctx, invocation, args(arguments(command, &["language"]))).await,
+        "history" => run_history(ctx, invocation).await,
+        "help" => run_help(ctx, invocation, args(arguments(command, &["command"]))).await,
+        "grant" => run_grant(ctx, invocation, args(arguments(command, &["user", "card"]))).await,
+        "revoke" => run_revoke(ctx, invocation, args(arguments(command, &["user", "card"]))).await,
+        "audit" => match argument(command, "card") {
+            Some(card_id) => run_audit(ctx, invocation, args(format!("card {}", card_id))).await,
+            None => run_audit(ctx, invocation, args(arguments(command, &["user"]))).await,
+        },
+        "reverse" => run_reverse(ctx, invocation, args(arguments(command, &["entry"]))).await,
+        "rebuildstats" => run_rebuildstats(ctx, invocation).await,
+        "modlog" => run_modlog(ctx, invocation, args(argument(command, "channel").unwrap_or_else(|| "off".to_string()))).await,
+        "prefix" => match argument(command, "prefix") {
+            Some(new_prefix) if new_prefix == "reset" => run_prefix(ctx, invocation, args(new_prefix)).await,
+            Some(new_prefix) => run_prefix(ctx, invocation, args(format!("set {}", new_prefix))).await,
+            None => run_prefix(ctx, invocation, args(String::new())).await,
+        },
+        "serverlanguage" => run_serverlanguage(ctx, invocation, args(arguments(command, &["language"]))).await,
+        "economy" => run_economy(ctx, invocation, args(arguments(command, &["mode"]))).await,
+        "channels" => run_channels(ctx, invocation, args(arguments(command, &["group", "action", "channel"]))).await,
+        "roles" => run_roles(ctx, invocation, args(arguments(command, &["tier", "action", "role"]))).await,
+        "catalog" => match catalog_action(command, &invocation.locale(ctx).await) {
+            Ok(action) => update_catalog(ctx, invocation, action).await,
+            Err(reason) => invocation.reply(ctx, reason).await.map(|_| ()).map_err(Into::into),
+        },
+        _ => Ok(()),
+    };
+    if let Err(why) = result {
+        error!("Slash command {} failed: {:?}", name, why);
+        let lang = invocation.locale(ctx).await;
+        let _ = invocation.reply(ctx, locale::error(&lang, &*why)).await;
+    }
+}
+
+/// Suggests catalog cards for the card option being typed.
+pub async fn autocomplete(ctx: &Context, autocomplete: &AutocompleteInteraction) {
+    let query = autocomplete.data.options.iter()
+        .find(|option| option.focused)
+        .and_then(|option| option.value.as_ref())
+        .and_then(|value| value.as_str())
+        .unwrap_or_default();
+    let cards = match catalog::catalog(ctx).await {
+        Ok(cards) => cards,
+        Err(why) => {
+            error!("Could not load the card catalog: {}", why);
+            return;
+        }
+    };
+    let matches = catalog::search(&cards, query, config::SUGGESTIONS);
+    let result = autocomplete.create_autocomplete_response(&ctx.http, |r| {
+        for card in matches {
+            let label: String = format!("{} - {} ({})", card.name, card.set, card.id).chars().take(100).collect();
+            r.add_string_choice(label, &card.id);
+        }
+        r
+    }).await;
+    if let Err(why) = result {
+        error!("Could not send autocomplete suggestions: {:?}", why);
+    }
+}
@@ -15,4 +15,54 @@ pub static POSTERSCATEGORYALTERNATES: [&str; 4] = [
 
 pub static ROLLTIME: i64 = 15;
 
-pub static INVTIME: i64 = 5;
\ No newline at end of file
+pub static INVTIME: i64 = 5;
+
+pub static HISTORYLENGTH: usize = 15;
+
+pub static TRADEEXPIRY: i64 = 60;
+
+// Trade safeguards against alt accounts funneling cards into a main account. Ages and windows are in days.
+pub static MINACCOUNTAGE: i64 = 14;
+
+pub static MINMEMBERAGE: i64 = 3;
+
+pub static DAILYTRADECAP: usize = 10;
+
+pub static FUNNELTHRESHOLD: usize = 5;
+
+pub static FUNNELWINDOW: i64 = 7;
+
+pub static UNDOTIME: i64 = 60;
+
+// Catalog rarities from most to least common. Cards without a rarity are the first tier.
+pub static RARITIES: [&str; 5] = [
+    "common",
+    "uncommon",
+    "rare",
+    "epic",
+    "legendary",
+];
+
+pub static LISTPAGESIZE: usize = 12;
+
+pub static PAGINATORTIMEOUT: u64 = 120;
+
+pub static GRIDPAGESIZE: usize = 9;
+
+pub static IMAGECACHEDIR: &str = "cache/images";
+
+pub static IMAGECACHESIZE: u64 = 256 * 1024 * 1024;
+
+pub static TOPHOLDERS: usize = 5;
+
+pub static PROFILESETS: usize = 8;
+
+pub static CATALOGREFRESH: u64 = 600;
+
+pub static SUGGESTIONS: usize = 10;
+
+pub static DEFAULTPREFIX: &str = "!";
+
+pub static MAXPREFIXLENGTH: usize = 5;
+
+pub static DEFAULTLOCALE: &str = "en";
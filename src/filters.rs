@@ -0,0 +1,288 @@
+use crate::config;
+use crate::firebase::GeneratedCard;
+use crate::locale::Localized;
+
+/// Splits command arguments on whitespace, keeping quoted values together so that
+/// `set:"Star Wars"` stays a single token.
+pub fn tokenize(input: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in input.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(current.clone());
+                    current.clear();
+                }
+            },
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+/// Filters for card listings, written as `set:"Marvel" theme:Horror name:batman dupes`.
+/// Text filters match case-insensitively on any part of the field.
+#[derive(Debug, Clone, Default)]
+pub struct CardFilter {
+    pub set: Option<String>,
+    pub theme: Option<String>,
+    pub name: Option<String>,
+    pub dupes: bool,
+    pub favorites: bool,
+    pub locked: bool,
+}
+
+impl CardFilter {
+    /// Takes the filter tokens out of `tokens` and returns the filter along with every
+    /// token it did not recognize, so commands can parse their own options from the rest.
+    pub fn parse(tokens: Vec<String>) -> (CardFilter, Vec<String>) {
+        let mut filter = CardFilter::default();
+        let mut rest = vec![];
+        for token in tokens {
+            let lowercase = token.to_lowercase();
+            if let Some(value) = lowercase.strip_prefix("set:") {
+                filter.set = Some(value.to_string());
+            } else if let Some(value) = lowercase.strip_prefix("theme:") {
+                filter.theme = Some(value.to_string());
+            } else if let Some(value) = lowercase.strip_prefix("name:") {
+                filter.name = Some(value.to_string());
+            } else if matches!(lowercase.as_str(), "dupes" | "duplicates") {
+                filter.dupes = true;
+            } else if matches!(lowercase.as_str(), "favorites" | "favs" | "fav") {
+                filter.favorites = true;
+            } else if lowercase == "locked" {
+                filter.locked = true;
+            } else {
+                rest.push(token);
+            }
+        }
+        (filter, rest)
+    }
+
+    pub fn matches(&self, card: &GeneratedCard) -> bool {
+        let contains = |field: &str, value: &Option<String>| {
+            value.as_ref().map(|value| field.to_lowercase().contains(value.as_str())).unwrap_or(true)
+        };
+        contains(&card.set, &self.set)
+            && contains(&card.theme, &self.theme)
+            && contains(&card.name, &self.name)
+            && (!self.dupes || card.quantity > 1)
+            && (!self.favorites || card.favorite)
+            && (!self.locked || card.locked)
+    }
+
+    pub fn apply(&self, cards: Vec<GeneratedCard>) -> Vec<GeneratedCard> {
+        cards.into_iter().filter(|card| self.matches(card)).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Name,
+    Set,
+    Theme,
+    Quantity,
+    Recent,
+    Rarity,
+}
+
+impl SortKey {
+    pub fn from_name(input: &str) -> Option<SortKey> {
+        match input {
+            "name" => Some(SortKey::Name),
+            "set" => Some(SortKey::Set),
+            "theme" => Some(SortKey::Theme),
+            "quantity" | "qty" => Some(SortKey::Quantity),
+            "recent" => Some(SortKey::Recent),
+            "rarity" => Some(SortKey::Rarity),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Set => "set",
+            SortKey::Theme => "theme",
+            SortKey::Quantity => "quantity",
+            SortKey::Recent => "recent",
+            SortKey::Rarity => "rarity",
+        }
+    }
+}
+
+/// Sort order for card listings, written as `sort:name` followed by an optional `asc` or
+/// `desc`. Text keys default to ascending; quantity, recent and rarity to descending.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CardSort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl CardSort {
+    pub fn new(key: SortKey) -> CardSort {
+        CardSort {
+            key,
+            descending: matches!(key, SortKey::Quantity | SortKey::Recent | SortKey::Rarity),
+        }
+    }
+
+    /// Takes the sort tokens out of `tokens`, returning the sort if one was given and the
+    /// tokens it did not recognize. An unknown `sort:` key is an error.
+    pub fn parse(tokens: Vec<String>) -> Result<(Option<CardSort>, Vec<String>), Localized> {
+        let mut sort: Option<CardSort> = None;
+        let mut direction = None;
+        let mut rest = vec![];
+        for token in tokens {
+            let lowercase = token.to_lowercase();
+            if let Some(value) = lowercase.strip_prefix("sort:") {
+                match SortKey::from_name(value) {
+                    Some(key) => sort = Some(CardSort::new(key)),
+                    None => return Err(Localized::new("sort.unknown").arg("sort", value)),
+                }
+            } else if matches!(lowercase.as_str(), "asc" | "ascending") {
+                direction = Some(false);
+            } else if matches!(lowercase.as_str(), "desc" | "descending") {
+                direction = Some(true);
+            } else {
+                rest.push(token);
+            }
+        }
+        if let (Some(sort), Some(descending)) = (sort.as_mut(), direction) {
+            sort.descending = descending;
+        }
+        Ok((sort, rest))
+    }
+
+    /// Reads a sort saved as a user setting, such as `name:asc`.
+    pub fn from_setting(setting: &str) -> Option<CardSort> {
+        let mut parts = setting.split(':');
+        let key = SortKey::from_name(parts.next()?)?;
+        Some(CardSort {
+            key,
+            descending: parts.next() == Some("desc"),
+        })
+    }
+
+    pub fn to_setting(self) -> String {
+        format!("{}:{}", self.key.as_str(), if self.descending { "desc" } else { "asc" })
+    }
+
+    pub fn apply(&self, cards: &mut [GeneratedCard]) {
+        let rarity = |card: &GeneratedCard| config::RARITIES.iter().position(|r| *r == card.rarity.to_lowercase()).unwrap_or(0);
+        match self.key {
+            SortKey::Name => cards.sort_by_key(|card| card.name.to_lowercase()),
+            SortKey::Set => cards.sort_by_key(|card| (card.set.to_lowercase(), card.name.to_lowercase())),
+            SortKey::Theme => cards.sort_by_key(|card| (card.theme.to_lowercase(), card.name.to_lowercase())),
+            SortKey::Quantity => cards.sort_by_key(|card| card.quantity),
+            SortKey::Recent => cards.sort_by_key(|card| card.acquired),
+            SortKey::Rarity => cards.sort_by_key(rarity),
+        }
+        if self.descending {
+            cards.reverse();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(name: &str, set: &str, quantity: u16, rarity: &str) -> GeneratedCard {
+        GeneratedCard {
+            set: set.to_string(),
+            quantity,
+            rarity: rarity.to_string(),
+            ..GeneratedCard::sample(&name.to_lowercase(), name)
+        }
+    }
+
+    #[test]
+    fn tokenize_keeps_quoted_values_together() {
+        assert_eq!(tokenize("set:\"Star Wars\" theme:Horror  dupes"), vec!["set:Star Wars", "theme:Horror", "dupes"]);
+        assert_eq!(tokenize("name:\"Iron Man\""), vec!["name:Iron Man"]);
+        assert_eq!(tokenize("\"a b\" c"), vec!["a b", "c"]);
+        assert!(tokenize("   ").is_empty());
+    }
+
+    #[test]
+    fn tokenize_closes_an_unterminated_quote_at_the_end() {
+        assert_eq!(tokenize("set:\"Star Wars"), vec!["set:Star Wars"]);
+    }
+
+    #[test]
+    fn card_filter_takes_its_tokens_and_leaves_the_rest() {
+        let (filter, rest) = CardFilter::parse(tokenize("set:\"Star Wars\" NAME:Vader favs list page:2"));
+        assert_eq!(filter.set.as_deref(), Some("star wars"));
+        assert_eq!(filter.name.as_deref(), Some("vader"));
+        assert!(filter.favorites && !filter.dupes && !filter.locked);
+        assert_eq!(rest, vec!["list", "page:2"]);
+    }
+
+    #[test]
+    fn card_filter_matches_any_part_of_a_field() {
+        let (filter, _) = CardFilter::parse(tokenize("set:\"star\" dupes"));
+        assert!(filter.matches(&card("Vader", "Star Wars", 2, "rare")));
+        assert!(!filter.matches(&card("Vader", "Star Wars", 1, "rare")));
+        assert!(!filter.matches(&card("Batman", "DC", 2, "rare")));
+    }
+
+    #[test]
+    fn card_filter_keeps_only_flagged_cards_when_asked() {
+        let mut favorite = card("Vader", "Star Wars", 1, "rare");
+        favorite.favorite = true;
+        let plain = card("Batman", "DC", 1, "rare");
+        let (filter, _) = CardFilter::parse(tokenize("favorites"));
+        let kept = filter.apply(vec![favorite, plain.clone()]);
+        assert_eq!(kept.iter().map(|card| card.name.as_str()).collect::<Vec<_>>(), vec!["Vader"]);
+        let (filter, _) = CardFilter::parse(tokenize(""));
+        assert_eq!(filter.apply(vec![plain]).len(), 1);
+    }
+
+    #[test]
+    fn card_sort_defaults_direction_by_key() {
+        let (sort, rest) = CardSort::parse(tokenize("sort:name grid")).unwrap();
+        assert_eq!(sort, Some(CardSort { key: SortKey::Name, descending: false }));
+        assert_eq!(rest, vec!["grid"]);
+        let (sort, _) = CardSort::parse(tokenize("sort:rarity")).unwrap();
+        assert_eq!(sort, Some(CardSort { key: SortKey::Rarity, descending: true }));
+    }
+
+    #[test]
+    fn card_sort_reads_an_explicit_direction() {
+        let (sort, _) = CardSort::parse(tokenize("desc sort:set")).unwrap();
+        assert_eq!(sort, Some(CardSort { key: SortKey::Set, descending: true }));
+        let (sort, rest) = CardSort::parse(tokenize("asc")).unwrap();
+        assert_eq!(sort, None);
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn card_sort_rejects_an_unknown_key() {
+        assert_eq!(CardSort::parse(tokenize("sort:color")).unwrap_err(), Localized::new("sort.unknown").arg("sort", "color"));
+    }
+
+    #[test]
+    fn card_sort_round_trips_through_a_setting() {
+        let sort = CardSort { key: SortKey::Quantity, descending: false };
+        assert_eq!(CardSort::from_setting(&sort.to_setting()), Some(sort));
+        assert_eq!(CardSort::from_setting("color:asc"), None);
+    }
+
+    #[test]
+    fn card_sort_orders_cards() {
+        let mut cards = vec![card("B", "X", 1, "rare"), card("a", "Y", 3, "common"), card("C", "X", 2, "legendary")];
+        CardSort::new(SortKey::Name).apply(&mut cards);
+        assert_eq!(cards.iter().map(|card| card.name.as_str()).collect::<Vec<_>>(), vec!["a", "B", "C"]);
+        CardSort::new(SortKey::Rarity).apply(&mut cards);
+        assert_eq!(cards.iter().map(|card| card.rarity.as_str()).collect::<Vec<_>>(), vec!["legendary", "rare", "common"]);
+        CardSort::new(SortKey::Quantity).apply(&mut cards);
+        assert_eq!(cards.iter().map(|card| card.quantity).collect::<Vec<_>>(), vec![3, 2, 1]);
+    }
+}
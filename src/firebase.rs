@@ -1,20 +1,19 @@
 use serde::Deserialize;
 use serde_json::{Value, json};
 use std::env;
+use std::collections::HashMap;
 use rand::{Rng, prelude::SliceRandom, SeedableRng};
-use chrono::{Utc, TimeZone, Duration};
+use chrono::{DateTime, Utc, TimeZone, Duration};
+use tracing::error;
 
 use crate::config;
-
-fn get_token() -> String {
-    env::var("FIREBASE_TOKEN").unwrap()
-}
+use crate::locale::Localized;
 
 fn get_project_id() -> String {
     env::var("PROJECT_ID").unwrap()
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct GeneratedCard {
     pub name: String,
     pub image: String,
@@ -23,7 +22,37 @@ pub struct GeneratedCard {
     pub theme: String,
     pub id: String,
     pub quantity: u16,
-    pub link: String
+    pub link: String,
+    pub rarity: String,
+    pub locked: bool,
+    pub favorite: bool,
+    /// Unix time the card was last added to the collection, 0 if unknown.
+    pub acquired: i64,
+    /// Taken out of the catalog: no longer rolled, but kept so owners can still find,
+    /// trade and view their copies.
+    pub retired: bool
+}
+
+#[cfg(test)]
+impl GeneratedCard {
+    /// A card with placeholder details, for tests to fill in the fields they care about.
+    pub fn sample(id: &str, name: &str) -> GeneratedCard {
+        GeneratedCard {
+            name: name.to_string(),
+            image: String::new(),
+            category: "characters".to_string(),
+            set: String::new(),
+            theme: String::new(),
+            id: id.to_string(),
+            quantity: 1,
+            link: String::new(),
+            rarity: config::RARITIES[0].to_string(),
+            locked: false,
+            favorite: false,
+            acquired: 0,
+            retired: false
+        }
+    }
 }
 
 pub fn rm_quotes(value: String) -> String {
@@ -33,306 +62,1034 @@ pub fn rm_quotes(value: String) -> String {
     chars.as_str().to_string()
 }
     
-pub async fn get_cards(category: String) -> Result<GeneratedCard, String> {
-    let mut request_url = format!("https://firestore.googleapis.com/v1/projects/{}/databases/(default)/documents/cards/{}/cards", get_project_id(), category);
-    let mut response = reqwest::get(request_url).await.unwrap();
-    let mut text = response.text().await.unwrap();
-    let mut v: Value = serde_json::from_str(text.as_str()).expect("Failed to parse JSON.");
-    let array: &mut Vec<Value> = &mut v["documents"].as_array().expect("Not an array").to_owned();
-    while v["nextPageToken"].is_string() {
-        request_url = format!("https://firestore.googleapis.com/v1/projects/{}/databases/(default)/documents/cards/{}/cards?pageToken={}", get_project_id(), category, v["nextPageToken"].as_str().unwrap());
-        response = reqwest::get(request_url).await.unwrap();
-        text = response.text().await.unwrap();
-        v = serde_json::from_str(text.as_str()).expect("Failed to parse JSON.");
-        let mut new_array = v["documents"].as_array().expect("Not an array while loop").to_owned();
-        array.append(&mut new_array);
-    }
-    let length = array.len();
-    let slice = array.choose(&mut rand::rngs::StdRng::from_entropy()).expect("Could not pick random number");
-    let rolled_name = rm_quotes(slice["fields"]["name"]["stringValue"].to_string());
-    let rolled_image = rm_quotes(slice["fields"]["image"]["stringValue"].to_string());
-    let rolled_category = rm_quotes(slice["fields"]["category"]["stringValue"].to_string());
-    let rolled_set = rm_quotes(slice["fields"]["set"]["stringValue"].to_string());
-    let rolled_theme = rm_quotes(slice["fields"]["theme"]["stringValue"].to_string());
-    let rolled_id = rm_quotes(slice["fields"]["id"]["stringValue"].to_string());
-    let mut rolled_link = rm_quotes(slice["fields"]["link"]["stringValue"].to_string());
+fn card_from_document(document: &Value, quantity: u16) -> GeneratedCard {
+    let rolled_name = rm_quotes(document["fields"]["name"]["stringValue"].to_string());
+    let rolled_image = rm_quotes(document["fields"]["image"]["stringValue"].to_string());
+    let rolled_category = rm_quotes(document["fields"]["category"]["stringValue"].to_string());
+    let rolled_set = rm_quotes(document["fields"]["set"]["stringValue"].to_string());
+    let rolled_theme = rm_quotes(document["fields"]["theme"]["stringValue"].to_string());
+    let rolled_id = rm_quotes(document["fields"]["id"]["stringValue"].to_string());
+    let mut rolled_link = rm_quotes(document["fields"]["link"]["stringValue"].to_string());
     if rolled_link == "ul" {
         rolled_link = String::new();
     }
-    let gen_card = GeneratedCard {
+    let mut rolled_rarity = rm_quotes(document["fields"]["rarity"]["stringValue"].to_string());
+    if rolled_rarity == "ul" {
+        rolled_rarity = config::RARITIES[0].to_string();
+    }
+    GeneratedCard {
         name: rolled_name,
         image: rolled_image,
         category: rolled_category,
         set: rolled_set,
         theme: rolled_theme,
         id: rolled_id,
-        quantity: 1,
-        link: rolled_link
-    };
-    Ok(gen_card)
+        quantity,
+        link: rolled_link,
+        rarity: rolled_rarity,
+        locked: false,
+        favorite: false,
+        acquired: 0,
+        retired: document["fields"]["retired"]["booleanValue"].as_bool().unwrap_or(false)
+    }
 }
 
-pub async fn get_card(card_id: String, quantity: u16, category: String) -> Result<GeneratedCard, ()> {
-    let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/cards/{category}/cards/{card_id}", project_id = get_project_id(), category = category, card_id = card_id);
-    let response = reqwest::get(request_url).await.unwrap();
-    let text = response.text().await.unwrap();
-    let v: Value = serde_json::from_str(text.as_str()).expect("Failed to parse JSON from response.");
-    let rolled_name = rm_quotes(v["fields"]["name"]["stringValue"].to_string());
-    let rolled_image = rm_quotes(v["fields"]["image"]["stringValue"].to_string());
-    let rolled_category = rm_quotes(v["fields"]["category"]["stringValue"].to_string());
-    let rolled_set = rm_quotes(v["fields"]["set"]["stringValue"].to_string());
-    let rolled_theme = rm_quotes(v["fields"]["theme"]["stringValue"].to_string());
-    let rolled_id = rm_quotes(v["fields"]["id"]["stringValue"].to_string());
-    let mut rolled_link = rm_quotes(v["fields"]["link"]["stringValue"].to_string());
-    if rolled_link == "ul" {
-        rolled_link = String::new();
+/// Every card in a category of the catalog.
+pub async fn get_catalog(category: String) -> Result<Vec<GeneratedCard>, Localized> {
+    let mut request_url = format!("https://firestore.googleapis.com/v1/projects/{}/databases/(default)/documents/cards/{}/cards", get_project_id(), category);
+    let mut response = reqwest::get(request_url).await.map_err(|e| e.to_string())?;
+    let mut text = response.text().await.map_err(|e| e.to_string())?;
+    let mut v: Value = serde_json::from_str(text.as_str()).map_err(|e| e.to_string())?;
+    let array: &mut Vec<Value> = &mut v["documents"].as_array().cloned().unwrap_or_default();
+    while v["nextPageToken"].is_string() {
+        request_url = format!("https://firestore.googleapis.com/v1/projects/{}/databases/(default)/documents/cards/{}/cards?pageToken={}", get_project_id(), category, v["nextPageToken"].as_str().unwrap());
+        response = reqwest::get(request_url).await.map_err(|e| e.to_string())?;
+        text = response.text().await.map_err(|e| e.to_string())?;
+        v = serde_json::from_str(text.as_str()).map_err(|e| e.to_string())?;
+        let mut new_array = v["documents"].as_array().cloned().unwrap_or_default();
+        array.append(&mut new_array);
+    }
+    Ok(array.iter().map(|document| card_from_document(document, 1)).collect())
+}
+
+/// Adds a card to the catalog of its category. Fails if the ID is already taken.
+pub async fn add_catalog_card(card: &GeneratedCard) -> Result<(), Localized> {
+    let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/cards/{category}/cards?documentId={card_id}", project_id = get_project_id(), category = card.category, card_id = card.id);
+    let data = json!({
+        "fields": {
+            "name": { "stringValue": card.name },
+            "image": { "stringValue": card.image },
+            "category": { "stringValue": card.category },
+            "set": { "stringValue": card.set },
+            "theme": { "stringValue": card.theme },
+            "id": { "stringValue": card.id },
+            "link": { "stringValue": card.link },
+            "rarity": { "stringValue": card.rarity }
+        }
+    });
+
+    let client = reqwest::Client::new();
+    let response = client.post(&request_url)
+        .json(&data)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else if response.status() == reqwest::StatusCode::CONFLICT {
+        Err(Localized::new("catalog.duplicate").arg("id", &card.id))
+    } else {
+        Err(Localized::new("catalog.add-failed"))
+    }
+}
+
+/// Retires a card so it can no longer be rolled. The card stays in the catalog, flagged, so
+/// the copies players already own keep their details.
+pub async fn remove_catalog_card(category: String, card_id: String) -> Result<(), Localized> {
+    let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/cards/{category}/cards/{card_id}?updateMask.fieldPaths=retired&currentDocument.exists=true", project_id = get_project_id(), category = category, card_id = card_id);
+    let data = json!({
+        "fields": {
+            "retired": { "booleanValue": true }
+        }
+    });
+    let client = reqwest::Client::new();
+    let response = client.patch(&request_url).json(&data).send().await.map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(Localized::new("catalog.remove-failed"))
     }
-    let gen_card = GeneratedCard {
-        name: rolled_name,
-        image: rolled_image,
-        category: rolled_category,
-        set: rolled_set,
-        theme: rolled_theme,
-        id: rolled_id,
-        quantity,
-        link: rolled_link
-    };
-    Ok(gen_card)
 }
 
-pub async fn fetch_inventory(user_id: String, category: String) -> Vec<GeneratedCard> {
-    let owned_cards = get_user_cards(user_id).await.expect("No cards found");
-    if owned_cards.is_empty() {
-        return vec![];
+pub async fn get_cards(category: String) -> Result<GeneratedCard, Localized> {
+    let catalog: Vec<GeneratedCard> = get_catalog(category.clone()).await?.into_iter().filter(|card| !card.retired).collect();
+    match catalog.choose(&mut rand::rngs::StdRng::from_entropy()) {
+        Some(card) => Ok(card.clone()),
+        None => Err(Localized::new("roll.empty").arg("category", category)),
     }
+}
+
+/// Looks a card up in the catalog of `category`, or `None` if it is not in that catalog.
+pub async fn get_card(card_id: String, quantity: u16, category: String) -> Result<Option<GeneratedCard>, Localized> {
+    let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/cards/{category}/cards/{card_id}", project_id = get_project_id(), category = category, card_id = card_id);
+    let response = reqwest::get(request_url).await.map_err(|e| e.to_string())?;
+    let text = response.text().await.map_err(|e| e.to_string())?;
+    let v: Value = serde_json::from_str(text.as_str()).map_err(|e| e.to_string())?;
+    if v["fields"].is_null() {
+        return Ok(None);
+    }
+    Ok(Some(card_from_document(&v, quantity)))
+}
+
+pub async fn fetch_inventory(user_id: String, category: String) -> Result<Vec<GeneratedCard>, Localized> {
+    let owned_cards = get_user_cards(user_id).await?;
     let mut display_vec = vec![];
     for card in owned_cards {
-        let card_details = get_card(card.id, card.quantity, category.clone()).await.expect("Card does not exist");
-        if card_details.category.to_lowercase() == category {
-            display_vec.push(card_details);
+        if let Some(mut card_details) = get_card(card.id, card.quantity, category.clone()).await? {
+            card_details.locked = card.locked;
+            card_details.favorite = card.favorite;
+            card_details.acquired = card.acquired;
+            if card_details.category.to_lowercase() == category {
+                display_vec.push(card_details);
+            }
         }
     }
-    display_vec
+    Ok(display_vec)
+}
+
+/// How many copies of a card the user owns.
+pub async fn owned_quantity(user_id: String, card_id: String) -> Result<u16, Localized> {
+    let cards = get_user_cards(user_id).await?;
+    Ok(cards.iter().find(|card| card.id == card_id).map(|card| card.quantity).unwrap_or(0))
 }
 
 struct CollectionCard {
     id: String,
     quantity: u16,
+    reserved: u16,
+    locked: bool,
+    favorite: bool,
+    acquired: i64,
+}
+
+impl CollectionCard {
+    fn new(id: String, quantity: u16) -> CollectionCard {
+        CollectionCard {
+            id,
+            quantity,
+            reserved: 0,
+            locked: false,
+            favorite: false,
+            acquired: Utc::now().timestamp(),
+        }
+    }
+
+    fn from_json(value: &Value) -> CollectionCard {
+        let fields = &value["mapValue"]["fields"];
+        CollectionCard {
+            id: rm_quotes(fields["id"]["stringValue"].to_string()),
+            quantity: rm_quotes(fields["quantity"]["stringValue"].to_string()).parse::<u16>().unwrap(),
+            reserved: fields["reserved"]["integerValue"].as_str().and_then(|s| s.parse().ok()).unwrap_or(0),
+            locked: fields["locked"]["booleanValue"].as_bool().unwrap_or(false),
+            favorite: fields["favorite"]["booleanValue"].as_bool().unwrap_or(false),
+            acquired: fields["acquired"]["timestampValue"].as_str()
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                .map(|t| t.timestamp())
+                .unwrap_or(0),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "mapValue": {
+                "fields": {
+                    "quantity": {
+                        "stringValue": self.quantity.to_string()
+                    },
+                    "id": {
+                        "stringValue": self.id
+                    },
+                    "reserved": {
+                        "integerValue": self.reserved.to_string()
+                    },
+                    "locked": {
+                        "booleanValue": self.locked
+                    },
+                    "favorite": {
+                        "booleanValue": self.favorite
+                    },
+                    "acquired": {
+                        "timestampValue": Utc.timestamp(self.acquired, 0).format("%Y-%m-%dT%H:%M:%SZ").to_string()
+                    }
+                }
+            }
+        })
+    }
 }
 
-async fn get_user_cards(user_id: String) -> Result<Vec<CollectionCard>, ()> {
+async fn get_user_document(user_id: String) -> Result<Option<Value>, Localized> {
     let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/users/{user_id}", project_id = get_project_id(), user_id = user_id);
-    let response = reqwest::get(&request_url).await.unwrap();
+    let response = reqwest::get(&request_url).await.map_err(|e| e.to_string())?;
     if response.status().is_client_error() {
-        return Ok(vec![]);
+        return Ok(None);
     }
-    let status = response.text().await.expect("No cards found");
-    
-    let v: Value = serde_json::from_str(status.as_str()).expect("No valid JSON");
-    let owned_cards = v["fields"]["cards"]["arrayValue"]["values"].as_array().unwrap().to_vec();
-    let mut collection = vec![];
+    let text = response.text().await.map_err(|e| e.to_string())?;
+    let v: Value = serde_json::from_str(text.as_str()).map_err(|e| e.to_string())?;
+    Ok(Some(v))
+}
 
-    for card in owned_cards {
-        let card_id: String = rm_quotes(card["mapValue"]["fields"]["id"]["stringValue"].to_string());
-        let card_quantity = rm_quotes(card["mapValue"]["fields"]["quantity"]["stringValue"].to_string()).parse::<u16>().unwrap();
-        let collection_card = CollectionCard {
-            id: card_id,
-            quantity: card_quantity
-        };
-        collection.push(collection_card)
+/// Who may look at a player's collection.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Privacy {
+    Public,
+    /// Only members of a server the player is in.
+    #[default]
+    Guild,
+    Private,
+}
+
+impl Privacy {
+    pub fn from_name(input: &str) -> Option<Privacy> {
+        match input {
+            "public" => Some(Privacy::Public),
+            "guild" | "server" => Some(Privacy::Guild),
+            "private" => Some(Privacy::Private),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Privacy::Public => "public",
+            Privacy::Guild => "guild",
+            Privacy::Private => "private",
+        }
     }
-    Ok(collection)
 }
 
-async fn create_user(id: String, json_value: Value) -> Result<(), ()> {
-        let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/users?documentId={user_id}", project_id = get_project_id(), user_id = id);
+/// Per-user preferences stored on the user document. See `Scope::key` for which document
+/// holds which of them.
+#[derive(Debug, Clone, Default)]
+pub struct UserSettings {
+    pub default_sort: Option<String>,
+    pub privacy: Privacy,
+    /// The card shown on the player's profile.
+    pub showcase: Option<String>,
+    /// Whether to tell the player when their roll is ready, and where. `None` means by DM.
+    pub remind: bool,
+    pub remind_channel: Option<u64>,
+    /// The language the bot answers this player in, over the language of the server.
+    pub locale: Option<String>,
+}
 
-        let client = reqwest::Client::new();
-        let response = client.post(request_url)
-            .json(&json_value)
-            .send()
-            .await;
-        
-        response.expect("Uh oh.").text().await.expect("Uh oh. 1");
+pub async fn get_user_settings(user_id: String) -> Result<UserSettings, Localized> {
+    let v = match get_user_document(user_id).await? {
+        Some(v) => v,
+        None => return Ok(UserSettings::default()),
+    };
+    let fields = &v["fields"];
+    Ok(UserSettings {
+        default_sort: fields["default_sort"]["stringValue"].as_str().map(|s| s.to_string()),
+        privacy: fields["privacy"]["stringValue"].as_str().and_then(Privacy::from_name).unwrap_or_default(),
+        showcase: fields["showcase"]["stringValue"].as_str().map(|s| s.to_string()),
+        remind: fields["remind"]["booleanValue"].as_bool().unwrap_or(false),
+        remind_channel: fields["remind_channel"]["stringValue"].as_str().and_then(|s| s.parse().ok()),
+        locale: fields["locale"]["stringValue"].as_str().map(|s| s.to_string()),
+    })
+}
+
+/// Writes a single field of the user document, creating the document if needed.
+pub async fn set_user_setting(user_id: String, field: &str, value: Value) -> Result<(), Localized> {
+    let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/users/{user_id}?updateMask.fieldPaths={field}", project_id = get_project_id(), user_id = user_id, field = field);
+    let data = json!({
+        "fields": {
+            field: value
+        }
+    });
+
+    let client = reqwest::Client::new();
+    let response = client.patch(&request_url)
+        .json(&data)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if response.status().is_success() {
         Ok(())
+    } else {
+        Err(Localized::new("settings.save-failed"))
     }
+}
 
-pub async fn save_card(user_id: String, card_id: String) -> Result<(), ()> {
-    let cards: Vec<CollectionCard> = get_user_cards(user_id.clone()).await?;
-    if cards.is_empty() {
-        let current_time = Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string();
-        let inv_time = (Utc::now() - Duration::minutes(6)).format("%Y-%m-%dT%H:%M:%SZ").to_string();
-        let json_data = json!({
-            "fields": {
-                "cards": {
-                    "arrayValue": {
-                        "values": [
-                            {
-                                "mapValue": {
-                                    "fields": {
-                                        "quantity": {
-                                            "stringValue": "1"
-                                        },
-                                        "id": {
-                                            "stringValue": card_id
-                                        }
-                                    }
-                                }
-                            }
-                        ]
+async fn get_user_cards(user_id: String) -> Result<Vec<CollectionCard>, Localized> {
+    Ok(get_user_cards_versioned(user_id).await?.0)
+}
+
+/// Adds copies of a card to a collection.
+fn add_copies(cards: &mut Vec<CollectionCard>, card_id: &str, quantity: u16) {
+    match cards.iter_mut().find(|card| card.id == card_id) {
+        Some(card) => {
+            card.quantity += quantity;
+            card.acquired = Utc::now().timestamp();
+        },
+        None => cards.push(CollectionCard::new(card_id.to_string(), quantity)),
+    }
+}
+
+/// Checks that `quantity` copies of a card can leave a collection: they must be owned,
+/// not held in escrow by a pending trade offer, and not locked (unless an admin action
+/// sets `ignore_lock`). Every path that takes cards from a player goes through here.
+fn check_available(card: Option<&CollectionCard>, card_id: &str, quantity: u16, ignore_lock: bool) -> Result<(), Localized> {
+    let card = match card {
+        Some(card) if card.quantity >= quantity => card,
+        _ => return Err(Localized::new("card.not-owned")),
+    };
+    if card.locked && !ignore_lock {
+        return Err(Localized::new("card.locked").arg("card", card_id));
+    }
+    if card.quantity - card.reserved < quantity {
+        return Err(Localized::new("card.in-escrow").arg("card", card_id));
+    }
+    Ok(())
+}
+
+/// Takes copies of a card out of a collection, if `check_available` allows it.
+fn take_copies(cards: &mut Vec<CollectionCard>, card_id: &str, quantity: u16, ignore_lock: bool) -> Result<(), Localized> {
+    check_available(cards.iter().find(|card| card.id == card_id), card_id, quantity, ignore_lock)?;
+    let index = cards.iter().position(|card| card.id == card_id).unwrap();
+    cards[index].quantity -= quantity;
+    if cards[index].quantity == 0 {
+        cards.remove(index);
+    }
+    Ok(())
+}
+
+/// Commit writes that count a roll on the user document and remember when the player
+/// first rolled.
+fn roll_writes(user_id: &str, document: Option<&Value>) -> Vec<Value> {
+    let mut writes = vec![json!({
+        "transform": {
+            "document": document_name(format!("users/{}", user_id)),
+            "fieldTransforms": [{
+                "fieldPath": "roll_count",
+                "increment": { "integerValue": "1" }
+            }]
+        }
+    })];
+    if document.map(|v| v["fields"]["first_rolled"].is_null()).unwrap_or(true) {
+        writes.insert(0, json!({
+            "update": {
+                "name": document_name(format!("users/{}", user_id)),
+                "fields": {
+                    "first_rolled": {
+                        "timestampValue": Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()
                     }
+                }
+            },
+            "updateMask": {
+                "fieldPaths": ["first_rolled"]
+            }
+        }));
+    }
+    writes
+}
+
+/// Adds a rolled card to the player's collection and counts the roll, recording it in the
+/// ledger and the card's counters in the same commit. The commit is retried a few times
+/// when the collection changes at the same moment, since the cooldown has already started.
+pub async fn save_card(user_id: String, card_id: String) -> Result<(), Localized> {
+    let mut result = Ok(());
+    for _ in 0..3 {
+        let document = get_user_document(user_id.clone()).await?;
+        let (mut cards, update_time) = versioned_cards(document.as_ref());
+        add_copies(&mut cards, &card_id, 1);
+        let mut writes = vec![user_cards_write(&user_id, &cards, update_time)];
+        writes.append(&mut roll_writes(&user_id, document.as_ref()));
+        writes.push(ledger_write(ledger_fields(user_id.clone(), None, card_id.clone(), 1, LedgerReason::Roll)).1);
+        writes.push(circulation_write(&card_id, &[(user_id.clone(), 1)]));
+        result = commit(writes).await;
+        if result.is_ok() {
+            break;
+        }
+    }
+    result?;
+    // Separate from the roll so a lost race for the first roll cannot undo it.
+    if let Err(why) = record_first_roller(card_id.clone(), user_id).await {
+        error!("Could not record the first roller of {}: {}", card_id, why);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct TradeOffer {
+    pub id: String,
+    pub from: String,
+    pub to: String,
+    pub card_id: String,
+    pub quantity: u16,
+    pub created: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+    pub status: String,
+    /// When the offer document was last written, for commit preconditions.
+    update_time: Option<String>,
+}
+
+impl TradeOffer {
+    fn from_document(document: &Value) -> Option<TradeOffer> {
+        let fields = &document["fields"];
+        let created = DateTime::parse_from_rfc3339(fields["created"]["timestampValue"].as_str()?).ok()?;
+        let expires = DateTime::parse_from_rfc3339(fields["expires"]["timestampValue"].as_str()?).ok()?;
+        Some(TradeOffer {
+            id: document["name"].as_str()?.rsplit('/').next()?.to_string(),
+            from: fields["from"]["stringValue"].as_str()?.to_string(),
+            to: fields["to"]["stringValue"].as_str()?.to_string(),
+            card_id: fields["card_id"]["stringValue"].as_str()?.to_string(),
+            quantity: fields["quantity"]["integerValue"].as_str()?.parse().ok()?,
+            created: created.with_timezone(&Utc),
+            expires: expires.with_timezone(&Utc),
+            status: fields["status"]["stringValue"].as_str()?.to_string(),
+            update_time: document["updateTime"].as_str().map(|s| s.to_string()),
+        })
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires < Utc::now()
+    }
+}
+
+fn generate_offer_id() -> String {
+    let alphabet: Vec<char> = "abcdefghjkmnpqrstuvwxyz23456789".chars().collect();
+    let mut rng = rand::rngs::StdRng::from_entropy();
+    (0..6).map(|_| alphabet[rng.gen_range(0..alphabet.len())]).collect()
+}
+
+/// Puts one copy of a card into escrow and creates a pending offer for the recipient, in a
+/// single commit that fails if the sender's collection changed since it was read.
+pub async fn create_trade_offer(from_user_id: String, card_id: String, to_user_id: String) -> Result<TradeOffer, Localized> {
+    let (mut cards, update_time) = get_user_cards_versioned(from_user_id.clone()).await?;
+    check_available(cards.iter().find(|card| card.id == card_id), &card_id, 1, false)?;
+    let card = cards.iter_mut().find(|card| card.id == card_id).unwrap();
+    card.reserved += 1;
+
+    let offer = TradeOffer {
+        id: generate_offer_id(),
+        from: from_user_id,
+        to: to_user_id,
+        card_id,
+        quantity: 1,
+        created: Utc::now(),
+        expires: Utc::now() + Duration::minutes(config::TRADEEXPIRY),
+        status: "pending".to_string(),
+        update_time: None,
+    };
+    let offer_write = json!({
+        "update": {
+            "name": document_name(format!("trades/{}", offer.id)),
+            "fields": {
+                "from": {
+                    "stringValue": offer.from
+                },
+                "to": {
+                    "stringValue": offer.to
                 },
-                "last_rolled": {
-                    "timestampValue": current_time
+                "card_id": {
+                    "stringValue": offer.card_id
                 },
-                "last_inventory": {
-                    "timestampValue": inv_time
+                "quantity": {
+                    "integerValue": offer.quantity.to_string()
+                },
+                "created": {
+                    "timestampValue": offer.created.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+                },
+                "expires": {
+                    "timestampValue": offer.expires.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+                },
+                "status": {
+                    "stringValue": offer.status
                 }
             }
-        });
-        create_user(user_id, json_data).await?;
-        return Ok(());
-    }
-    let mut new_cards = vec![];
-    let mut found = false;
-    for mut card in cards {
-        if card.id == card_id {
-            card.quantity += 1;
-            found = true;
-        }
-        let json_value = json!({
-            "mapValue": {
-                "fields": {
-                    "quantity": {
-                        "stringValue": card.quantity.to_string()
-                    },
-                    "id": {
-                        "stringValue": card.id
-                    }
+        },
+        "currentDocument": {
+            "exists": false
+        }
+    });
+    commit(vec![user_cards_write(&offer.from, &cards, update_time), offer_write]).await
+        .map_err(|_| Localized::new("trade.create-failed"))?;
+    Ok(offer)
+}
+
+pub async fn get_trade_offer(offer_id: String) -> Result<TradeOffer, Localized> {
+    let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/trades/{offer_id}", project_id = get_project_id(), offer_id = offer_id);
+    let response = reqwest::get(request_url).await.map_err(|e| e.to_string())?;
+    let text = response.text().await.map_err(|e| e.to_string())?;
+    let v: Value = serde_json::from_str(text.as_str()).map_err(|e| e.to_string())?;
+    TradeOffer::from_document(&v).ok_or_else(|| Localized::new("trade.not-found").arg("offer", offer_id))
+}
+
+/// Closes a pending offer with `status` in a single commit, on condition the offer has not
+/// changed since it was read, so an offer is only ever closed once. The escrowed copy goes
+/// back to the sender, or to the recipient along with the ledger entry and counters when the
+/// offer is accepted.
+async fn close_trade_offer(offer: &TradeOffer, status: &str) -> Result<(), Localized> {
+    let update_time = offer.update_time.clone().ok_or_else(|| Localized::new("trade.unreadable").arg("offer", &offer.id))?;
+    let accepted = status == "accepted";
+    let mut writes = vec![json!({
+        "update": {
+            "name": document_name(format!("trades/{}", offer.id)),
+            "fields": {
+                "status": {
+                    "stringValue": status
                 }
             }
-        });
-        new_cards.push(json_value);
+        },
+        "updateMask": {
+            "fieldPaths": ["status"]
+        },
+        "currentDocument": {
+            "updateTime": update_time
+        }
+    })];
+
+    let (mut cards, sender_time) = get_user_cards_versioned(offer.from.clone()).await?;
+    let index = match cards.iter().position(|card| card.id == offer.card_id && card.reserved >= offer.quantity) {
+        Some(index) => index,
+        None => return Err(Localized::new("card.not-in-escrow").arg("card", &offer.card_id)),
+    };
+    cards[index].reserved -= offer.quantity;
+    if accepted {
+        cards[index].quantity -= offer.quantity;
+        if cards[index].quantity == 0 {
+            cards.remove(index);
+        }
     }
-    if !found {
-        let json_value = json!({
-            "mapValue": {
-                "fields": {
-                    "quantity": {
-                        "stringValue": "1"
-                    },
-                    "id": {
-                        "stringValue": card_id
-                    }
-                }
+    writes.push(user_cards_write(&offer.from, &cards, sender_time));
+
+    if accepted {
+        let (mut cards, recipient_time) = get_user_cards_versioned(offer.to.clone()).await?;
+        add_copies(&mut cards, &offer.card_id, offer.quantity);
+        writes.push(user_cards_write(&offer.to, &cards, recipient_time));
+        writes.push(ledger_write(ledger_fields(offer.from.clone(), Some(offer.to.clone()), offer.card_id.clone(), offer.quantity, LedgerReason::Trade)).1);
+        let quantity = offer.quantity as i64;
+        writes.push(circulation_write(&offer.card_id, &[(offer.from.clone(), -quantity), (offer.to.clone(), quantity)]));
+    }
+    commit(writes).await
+}
+
+/// Completes a pending offer: the escrowed copy leaves the sender and goes to the recipient.
+pub async fn accept_trade_offer(offer_id: String, user_id: String) -> Result<TradeOffer, Localized> {
+    let offer = get_trade_offer(offer_id).await?;
+    if offer.to != user_id || offer.status != "pending" {
+        return Err(Localized::new("trade.not-yours").arg("offer", &offer.id));
+    }
+    if offer.is_expired() {
+        close_trade_offer(&offer, "expired").await?;
+        return Err(Localized::new("trade.expired").arg("offer", &offer.id));
+    }
+    close_trade_offer(&offer, "accepted").await?;
+    Ok(offer)
+}
+
+/// Withdraws a pending offer and releases the escrow. The sender cancels, the recipient declines.
+pub async fn cancel_trade_offer(offer_id: String, user_id: String) -> Result<TradeOffer, Localized> {
+    let offer = get_trade_offer(offer_id).await?;
+    let status = if offer.from == user_id {
+        "cancelled"
+    } else if offer.to == user_id {
+        "declined"
+    } else {
+        return Err(Localized::new("trade.not-yours").arg("offer", &offer.id));
+    };
+    if offer.status != "pending" {
+        return Err(Localized::new("trade.not-pending").arg("offer", &offer.id));
+    }
+    close_trade_offer(&offer, status).await?;
+    Ok(offer)
+}
+
+async fn query_trade_offers(field: &str, value: String) -> Result<Vec<TradeOffer>, Localized> {
+    let documents = run_query(json!({
+        "from": [{ "collectionId": "trades" }],
+        "where": {
+            "fieldFilter": {
+                "field": { "fieldPath": field },
+                "op": "EQUAL",
+                "value": { "stringValue": value }
             }
-        });
-        new_cards.push(json_value);
+        }
+    })).await?;
+    Ok(documents.iter().filter_map(TradeOffer::from_document).collect())
+}
+
+/// Pending offers sent or received by the user, soonest to expire first.
+pub async fn pending_trade_offers(user_id: String) -> Result<Vec<TradeOffer>, Localized> {
+    let mut offers = query_trade_offers("from", user_id.clone()).await?;
+    offers.append(&mut query_trade_offers("to", user_id).await?);
+    offers.retain(|offer| offer.status == "pending" && !offer.is_expired());
+    offers.sort_by_key(|offer| offer.expires);
+    Ok(offers)
+}
+
+/// When each trade offer the user has sent since `since` was created, whatever became of
+/// them, oldest first.
+pub async fn trade_offers_sent_since(user_id: String, since: DateTime<Utc>) -> Result<Vec<DateTime<Utc>>, Localized> {
+    let offers = query_trade_offers("from", user_id).await?;
+    let mut created: Vec<DateTime<Utc>> = offers.iter().map(|offer| offer.created).filter(|created| *created >= since).collect();
+    created.sort();
+    Ok(created)
+}
+
+/// Releases the escrow of every pending offer past its expiry. Run periodically from `main`.
+/// An offer that cannot be expired is logged and retried on the next run without holding up
+/// the others.
+pub async fn expire_trade_offers() -> Result<usize, Localized> {
+    let offers = query_trade_offers("status", "pending".to_string()).await?;
+    let mut expired = 0;
+    for offer in offers.iter().filter(|offer| offer.is_expired()) {
+        if let Err(why) = close_trade_offer(offer, "expired").await {
+            error!("Could not expire trade offer {}: {}", offer.id, why);
+            continue;
+        }
+        expired += 1;
     }
-    let request_url = format!("https://firestore.googleapis.com/v1beta1/projects/{project_id}/databases/(default)/documents/users/{user_id}?updateMask.fieldPaths=cards", project_id = get_project_id(), user_id = user_id);
+    Ok(expired)
+}
 
-    let patch_data = json!({
-        "fields": {
-            "cards": {
-                "arrayValue": {
-                    "values": new_cards.to_vec()
-                }
+/// Gives a player a copy of a card, recording the grant in the ledger and the card's
+/// counters in the same commit.
+pub async fn grant_card(admin_id: String, card_id: String, to_user_id: String) -> Result<(), Localized> {
+    let (mut cards, update_time) = get_user_cards_versioned(to_user_id.clone()).await?;
+    add_copies(&mut cards, &card_id, 1);
+    commit(vec![
+        user_cards_write(&to_user_id, &cards, update_time),
+        ledger_write(ledger_fields(admin_id, Some(to_user_id.clone()), card_id.clone(), 1, LedgerReason::Grant)).1,
+        circulation_write(&card_id, &[(to_user_id, 1)]),
+    ]).await
+}
+
+/// Takes a copy of a card from a player, even a locked one, recording the revocation in the
+/// ledger and the card's counters in the same commit.
+pub async fn revoke_card(admin_id: String, card_id: String, from_user_id: String) -> Result<(), Localized> {
+    let (mut cards, update_time) = get_user_cards_versioned(from_user_id.clone()).await?;
+    take_copies(&mut cards, &card_id, 1, true).map_err(|_| Localized::new("revoke.not-owned"))?;
+    commit(vec![
+        user_cards_write(&from_user_id, &cards, update_time),
+        ledger_write(ledger_fields(admin_id, Some(from_user_id.clone()), card_id.clone(), 1, LedgerReason::Revoke)).1,
+        circulation_write(&card_id, &[(from_user_id, -1)]),
+    ]).await
+}
+
+/// A per-card setting stored on the owned card entry.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CardFlag {
+    Locked,
+    Favorite,
+}
+
+pub async fn set_card_flag(user_id: String, card_id: String, flag: CardFlag, value: bool) -> Result<(), Localized> {
+    let (mut cards, update_time) = get_user_cards_versioned(user_id.clone()).await?;
+    let card = match cards.iter_mut().find(|card| card.id == card_id) {
+        Some(card) => card,
+        None => return Err(Localized::new("card.not-owned")),
+    };
+    match flag {
+        CardFlag::Locked => card.locked = value,
+        CardFlag::Favorite => card.favorite = value,
+    }
+    commit(vec![user_cards_write(&user_id, &cards, update_time)]).await
+}
+
+/// Why a card moved. Stored on every ledger entry.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LedgerReason {
+    Roll,
+    Trade,
+    Grant,
+    Revoke,
+    Undo,
+    Reversal,
+}
+
+impl LedgerReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LedgerReason::Roll => "roll",
+            LedgerReason::Trade => "trade",
+            LedgerReason::Grant => "grant",
+            LedgerReason::Revoke => "revoke",
+            LedgerReason::Undo => "undo",
+            LedgerReason::Reversal => "reversal",
+        }
+    }
+}
+
+/// An immutable record of a card movement. `actor` is whoever caused the
+/// movement; `counterparty` is the other user involved, if any.
+#[derive(Debug, Clone)]
+pub struct LedgerEntry {
+    pub id: String,
+    pub actor: String,
+    pub counterparty: Option<String>,
+    pub card_id: String,
+    pub quantity: u16,
+    pub reason: String,
+    pub timestamp: DateTime<Utc>,
+    pub reverses: Option<String>,
+    pub by: Option<String>,
+    /// The collections a reversal moved the card out of and into.
+    from: Option<String>,
+    to: Option<String>,
+}
+
+impl LedgerEntry {
+    fn from_document(document: &Value) -> Option<LedgerEntry> {
+        let fields = &document["fields"];
+        let timestamp = DateTime::parse_from_rfc3339(fields["timestamp"]["timestampValue"].as_str()?).ok()?;
+        Some(LedgerEntry {
+            id: document["name"].as_str()?.rsplit('/').next()?.to_string(),
+            actor: fields["actor"]["stringValue"].as_str()?.to_string(),
+            counterparty: fields["counterparty"]["stringValue"].as_str().map(|s| s.to_string()),
+            card_id: fields["card_id"]["stringValue"].as_str()?.to_string(),
+            quantity: fields["quantity"]["integerValue"].as_str()?.parse().ok()?,
+            reason: fields["reason"]["stringValue"].as_str()?.to_string(),
+            timestamp: timestamp.with_timezone(&Utc),
+            reverses: fields["reverses"]["stringValue"].as_str().map(|s| s.to_string()),
+            by: fields["by"]["stringValue"].as_str().map(|s| s.to_string()),
+            from: fields["from"]["stringValue"].as_str().map(|s| s.to_string()),
+            to: fields["to"]["stringValue"].as_str().map(|s| s.to_string()),
+        })
+    }
+
+    /// The collections the card left and entered, if any. A reversal records both, since a
+    /// reversed revocation has an admin actor and a card that came from no collection.
+    fn movement(&self) -> (Option<String>, Option<String>) {
+        if self.reverses.is_some() {
+            return (self.from.clone(), self.to.clone());
+        }
+        match self.reason.as_str() {
+            "roll" => (None, Some(self.actor.clone())),
+            "grant" => (None, self.counterparty.clone()),
+            "revoke" => (self.counterparty.clone(), None),
+            _ => (Some(self.actor.clone()), self.counterparty.clone()),
+        }
+    }
+
+    /// The players the entry involves. The actor of a grant, revocation or admin reversal is
+    /// the admin, not a player.
+    pub fn players(&self) -> Vec<&str> {
+        let admin_actor = matches!(self.reason.as_str(), "grant" | "revoke") || self.by.as_deref() == Some(self.actor.as_str());
+        let mut players = vec![];
+        if !admin_actor {
+            players.push(self.actor.as_str());
+        }
+        if let Some(counterparty) = &self.counterparty {
+            players.push(counterparty.as_str());
+        }
+        players
+    }
+}
+
+fn ledger_fields(actor: String, counterparty: Option<String>, card_id: String, quantity: u16, reason: LedgerReason) -> Value {
+    let counterparty_value = match counterparty {
+        Some(id) => json!({ "stringValue": id }),
+        None => json!({ "nullValue": null }),
+    };
+    json!({
+        "actor": {
+            "stringValue": actor
+        },
+        "counterparty": counterparty_value,
+        "card_id": {
+            "stringValue": card_id
+        },
+        "quantity": {
+            "integerValue": quantity.to_string()
+        },
+        "reason": {
+            "stringValue": reason.as_str()
+        },
+        "timestamp": {
+            "timestampValue": Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()
+        }
+    })
+}
+
+pub async fn get_ledger_entry(entry_id: String) -> Result<LedgerEntry, Localized> {
+    let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/ledger/{entry_id}", project_id = get_project_id(), entry_id = entry_id);
+    let response = reqwest::get(request_url).await.map_err(|e| e.to_string())?;
+    let text = response.text().await.map_err(|e| e.to_string())?;
+    let v: Value = serde_json::from_str(text.as_str()).map_err(|e| e.to_string())?;
+    LedgerEntry::from_document(&v).ok_or_else(|| Localized::new("ledger.not-found").arg("entry", entry_id))
+}
+
+async fn run_query(structured_query: Value) -> Result<Vec<Value>, Localized> {
+    let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents:runQuery", project_id = get_project_id());
+    let client = reqwest::Client::new();
+    let response = client.post(&request_url)
+        .json(&json!({ "structuredQuery": structured_query }))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let text = response.text().await.map_err(|e| e.to_string())?;
+    let v: Value = serde_json::from_str(text.as_str()).map_err(|e| e.to_string())?;
+    let results = v.as_array().ok_or_else(|| Localized::new("query.failed").arg("error", &v["error"]["message"]))?;
+    Ok(results.iter().filter(|result| result["document"].is_object()).map(|result| result["document"].clone()).collect())
+}
+
+async fn query_ledger(field: &str, value: String) -> Result<Vec<LedgerEntry>, Localized> {
+    let documents = run_query(json!({
+        "from": [{ "collectionId": "ledger" }],
+        "where": {
+            "fieldFilter": {
+                "field": { "fieldPath": field },
+                "op": "EQUAL",
+                "value": { "stringValue": value }
             }
         }
-    });
+    })).await?;
+    Ok(documents.iter().filter_map(LedgerEntry::from_document).collect())
+}
 
+/// Every ledger entry the user took part in, newest first.
+pub async fn ledger_for_user(user_id: String) -> Result<Vec<LedgerEntry>, Localized> {
+    let mut entries = query_ledger("actor", user_id.clone()).await?;
+    for entry in query_ledger("counterparty", user_id).await? {
+        if !entries.iter().any(|e| e.id == entry.id) {
+            entries.push(entry);
+        }
+    }
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.timestamp));
+    Ok(entries)
+}
+
+/// Every ledger entry for a card, newest first.
+pub async fn ledger_for_card(card_id: String) -> Result<Vec<LedgerEntry>, Localized> {
+    let mut entries = query_ledger("card_id", card_id).await?;
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.timestamp));
+    Ok(entries)
+}
+
+fn document_name(path: String) -> String {
+    format!("projects/{project_id}/databases/(default)/documents/{path}", project_id = get_project_id(), path = path)
+}
 
+/// Applies every write or none of them. Writes carry preconditions, so the commit fails
+/// if any document changed since it was read.
+async fn commit(writes: Vec<Value>) -> Result<(), Localized> {
+    let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents:commit", project_id = get_project_id());
     let client = reqwest::Client::new();
-    let response = client.patch(&request_url)
-        .json(&patch_data)
+    let response = client.post(&request_url)
+        .json(&json!({ "writes": writes }))
         .send()
-        .await;
-        
-    response.expect("Uh oh.").text().await.expect("Uh oh. 1");
+        .await
+        .map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(Localized::new("cards.changed"))
+    }
+}
 
-    Ok(())
+/// Reads a user's cards along with the document's update time, for use as a commit precondition.
+async fn get_user_cards_versioned(user_id: String) -> Result<(Vec<CollectionCard>, Option<String>), Localized> {
+    Ok(versioned_cards(get_user_document(user_id).await?.as_ref()))
 }
 
-pub async fn trade_card(from_user_id: String, card_id: String, to_user_id: String) -> Result<(), String> {
-    let collection = get_user_cards(from_user_id.clone()).await.expect("Failed to get user cards.");
-    let mut short_collection = vec![];
-    let mut found = false;
-    for mut card in collection {
-        if card.id == card_id {
-            card.quantity -= 1;
-            found = true;
-        }
-        if card.quantity != 0 {
-            let json_value = json!({
-                "mapValue": {
-                    "fields": {
-                        "quantity": {
-                            "stringValue": card.quantity.to_string()
-                        },
-                        "id": {
-                            "stringValue": card.id
-                        }
-                    }
-                }
-            });
-            short_collection.push(json_value);
-        }
-    }
-    if found {
-        if short_collection.to_vec().is_empty() {
-            let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/users/{user_id}", project_id = get_project_id(), user_id = from_user_id);
-            let client = reqwest::Client::new();
-            let response = client.delete(&request_url).send().await;
-            response.expect("Failed to delete user");
-        } else {
-            let patch_data = json!({
-                "fields": {
-                    "cards": {
-                        "arrayValue": {
-                            "values": short_collection.to_vec()
-                        }
+fn versioned_cards(document: Option<&Value>) -> (Vec<CollectionCard>, Option<String>) {
+    match document {
+        Some(v) => {
+            let owned_cards = v["fields"]["cards"]["arrayValue"]["values"].as_array().cloned().unwrap_or_default();
+            (owned_cards.iter().map(CollectionCard::from_json).collect(), v["updateTime"].as_str().map(|s| s.to_string()))
+        },
+        None => (vec![], None),
+    }
+}
+
+fn user_cards_write(user_id: &str, cards: &[CollectionCard], update_time: Option<String>) -> Value {
+    let values: Vec<Value> = cards.iter().map(CollectionCard::to_json).collect();
+    let precondition = match update_time {
+        Some(update_time) => json!({ "updateTime": update_time }),
+        None => json!({ "exists": false }),
+    };
+    json!({
+        "update": {
+            "name": document_name(format!("users/{}", user_id)),
+            "fields": {
+                "cards": {
+                    "arrayValue": {
+                        "values": values
                     }
                 }
-            });
-            let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/users/{user_id}", project_id = get_project_id(), user_id = from_user_id);
-            let client = reqwest::Client::new();
-            let response = client.patch(&request_url)
-                .json(&patch_data)
-                .send()
-                .await;
-                
-            response.expect("Uh oh.").text().await.expect("Uh oh. 1");
-        }
-        save_card(to_user_id, card_id).await.expect("Could not save card");
-        Ok(())
-    } else {
-        Err("You do not have this card.".to_string())
+            }
+        },
+        "updateMask": {
+            "fieldPaths": ["cards"]
+        },
+        "currentDocument": precondition
+    })
+}
+
+/// A commit write that appends a new ledger entry, along with the entry's ID.
+fn ledger_write(fields: Value) -> (String, Value) {
+    let ledger_id: String = rand::rngs::StdRng::from_entropy().sample_iter(&rand::distributions::Alphanumeric).take(20).map(char::from).collect();
+    let write = json!({
+        "update": {
+            "name": document_name(format!("ledger/{}", ledger_id)),
+            "fields": fields
+        },
+        "currentDocument": {
+            "exists": false
+        }
+    });
+    (ledger_id, write)
+}
+
+/// The ledger entry of a reversal, which moves the card the opposite way to `entry`.
+fn reversal_fields(entry: &LedgerEntry, by: String, reason: LedgerReason) -> Value {
+    let (source, destination) = entry.movement();
+    let mut fields = ledger_fields(destination.clone().unwrap_or_else(|| by.clone()), source.clone(), entry.card_id.clone(), entry.quantity, reason);
+    fields["reverses"] = json!({ "stringValue": entry.id });
+    fields["by"] = json!({ "stringValue": by });
+    for (field, user) in [("from", destination), ("to", source)] {
+        fields[field] = match user {
+            Some(id) => json!({ "stringValue": id }),
+            None => json!({ "nullValue": null }),
+        };
     }
+    fields
 }
 
-pub async fn check_roll_time(user_id: String) -> Result<bool, String> {
-    let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/users/{user_id}", project_id = get_project_id(), user_id = user_id);
-    let response = reqwest::get(request_url).await.unwrap();
-    let text = response.text().await.unwrap();
-    let v: Value = serde_json::from_str(text.as_str()).expect("Failed to parse JSON from response.");
-    let raw_date = rm_quotes(v["fields"]["last_rolled"]["timestampValue"].to_string());
-    if raw_date == "ul" {
-        return Ok(true);
+/// Moves the cards of a ledger entry back where they came from, recording the reversal in
+/// the same commit. Fails if the cards have since moved on or the entry was already reversed.
+async fn reverse_entry(entry: &LedgerEntry, by: String, reason: LedgerReason) -> Result<String, Localized> {
+    if entry.reverses.is_some() {
+        return Err(Localized::new("reverse.reversal"));
     }
-    let last_rolled = Utc.datetime_from_str(raw_date.as_str(), "%Y-%m-%dT%H:%M:%SZ").expect("Invalid date");
-    let current_time = Utc::now();
-    let diff = (current_time - last_rolled).num_minutes();
-    if diff > config::ROLLTIME {
-        let update_status = update_roll_time(user_id).await;
-        if update_status.is_err() {
-            return Err("Could not update the status".to_string());
+    if !query_ledger("reverses", entry.id.clone()).await?.is_empty() {
+        return Err(Localized::new("reverse.already").arg("entry", &entry.id));
+    }
+    let (source, destination) = entry.movement();
+    let later_entries = query_ledger("card_id", entry.card_id.clone()).await?;
+    let moved_on = later_entries.iter().any(|later| {
+        later.id != entry.id && later.timestamp >= entry.timestamp && later.movement().0.is_some() && later.movement().0 == destination
+    });
+    if moved_on {
+        return Err(Localized::new("reverse.moved").arg("card", &entry.card_id));
+    }
+
+    let mut writes = vec![];
+    if let Some(destination) = destination.clone() {
+        let (mut cards, update_time) = get_user_cards_versioned(destination.clone()).await?;
+        check_available(cards.iter().find(|card| card.id == entry.card_id), &entry.card_id, entry.quantity, true)
+            .map_err(|_| Localized::new("reverse.moved").arg("card", &entry.card_id))?;
+        let index = cards.iter().position(|card| card.id == entry.card_id).unwrap();
+        cards[index].quantity -= entry.quantity;
+        if cards[index].quantity == 0 {
+            cards.remove(index);
         }
-        return Ok(true);
+        writes.push(user_cards_write(&destination, &cards, update_time));
+    }
+    if let Some(source) = source.clone() {
+        let (mut cards, update_time) = get_user_cards_versioned(source.clone()).await?;
+        match cards.iter_mut().find(|card| card.id == entry.card_id) {
+            Some(card) => card.quantity += entry.quantity,
+            None => cards.push(CollectionCard::new(entry.card_id.clone(), entry.quantity)),
+        }
+        writes.push(user_cards_write(&source, &cards, update_time));
+    }
+
+    let quantity = entry.quantity as i64;
+    let mut changes = vec![];
+    if let Some(destination) = destination.clone() {
+        changes.push((destination, -quantity));
+    }
+    if let Some(source) = source.clone() {
+        changes.push((source, quantity));
+    }
+    writes.push(circulation_write(&entry.card_id, &changes));
+
+    let (ledger_id, write) = ledger_write(reversal_fields(entry, by, reason));
+    writes.push(write);
+    commit(writes).await?;
+    Ok(ledger_id)
+}
+
+/// Lets a sender take back their latest trade within `UNDOTIME` seconds, as long as the
+/// recipient has not moved the card yet.
+pub async fn undo_trade(user_id: String) -> Result<LedgerEntry, Localized> {
+    let entries = query_ledger("actor", user_id.clone()).await?;
+    let latest = entries.into_iter()
+        .filter(|entry| entry.reason == "trade")
+        .max_by_key(|entry| entry.timestamp);
+    let entry = match latest {
+        Some(entry) if (Utc::now() - entry.timestamp).num_seconds() <= config::UNDOTIME => entry,
+        _ => return Err(Localized::new("undo.none").arg("seconds", config::UNDOTIME)),
+    };
+    reverse_entry(&entry, user_id, LedgerReason::Undo).await?;
+    Ok(entry)
+}
+
+/// Admin reversal of a recorded movement.
+pub async fn reverse_ledger_entry(entry: &LedgerEntry, admin_id: String) -> Result<(), Localized> {
+    reverse_entry(entry, admin_id, LedgerReason::Reversal).await?;
+    Ok(())
+}
+
+/// When an action gated by `field` becomes available again, or `None` if it already is.
+fn cooldown_end(document: &Value, field: &str, minutes: i64) -> Option<DateTime<Utc>> {
+    let last_used = DateTime::parse_from_rfc3339(document["fields"][field]["timestampValue"].as_str()?).ok()?;
+    let end = last_used.with_timezone(&Utc) + Duration::minutes(minutes);
+    (end > Utc::now()).then_some(end)
+}
+
+/// Starts the roll cooldown if it has run out. Returns when the user can roll again if it has not.
+pub async fn check_roll_time(user_id: String) -> Result<Option<DateTime<Utc>>, Localized> {
+    if let Some(end) = get_user_document(user_id.clone()).await?.and_then(|v| cooldown_end(&v, "last_rolled", config::ROLLTIME)) {
+        return Ok(Some(end));
     }
-    Ok(false)
+    update_roll_time(user_id).await.map_err(|_| Localized::new("status.update-failed"))?;
+    Ok(None)
 }
 
-async fn update_roll_time(user_id: String) -> Result<(), String> {
+async fn update_roll_time(user_id: String) -> Result<(), Localized> {
     let request_url = format!("https://firestore.googleapis.com/v1beta1/projects/{project_id}/databases/(default)/documents/users/{user_id}?updateMask.fieldPaths=last_rolled&alt=json", project_id = get_project_id(), user_id = user_id);
     let current_time = Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string();
     let data = json!({
@@ -347,35 +1104,158 @@ async fn update_roll_time(user_id: String) -> Result<(), String> {
     let response = client.patch(&request_url)
         .json(&data)
         .send()
-        .await;
-        
-    response.expect("Uh oh.").text().await.expect("Uh oh. 1");
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(Localized::new("status.update-failed"));
+    }
+
+    // The cooldown has already started, so a reminder that cannot be scheduled must not cost
+    // the player their roll.
+    if let Err(why) = schedule_roll_reminder(user_id.clone()).await {
+        error!("Could not schedule a roll reminder for {}: {}", user_id, why);
+    }
     Ok(())
 }
 
-pub async fn check_inventory_time(user_id: String) -> Result<bool, String> {
-    let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/users/{user_id}", project_id = get_project_id(), user_id = user_id);
-    let response = reqwest::get(request_url).await.unwrap();
-    let text = response.text().await.unwrap();
-    let v: Value = serde_json::from_str(text.as_str()).expect("Failed to parse JSON from response.");
-    let raw_date = rm_quotes(v["fields"]["last_inventory"]["timestampValue"].to_string());
-    if raw_date == "ul" {
-        return Ok(true);
+async fn schedule_roll_reminder(user_id: String) -> Result<(), Localized> {
+    let settings = get_user_settings(user_id.clone()).await?;
+    if settings.remind {
+        schedule_reminder(user_id, Utc::now() + Duration::minutes(config::ROLLTIME), settings.remind_channel).await?;
+    }
+    Ok(())
+}
+
+/// Starts the inventory cooldown if it has run out. Returns when the user can look again if it has not.
+pub async fn check_inventory_time(user_id: String) -> Result<Option<DateTime<Utc>>, Localized> {
+    if let Some(end) = get_user_document(user_id.clone()).await?.and_then(|v| cooldown_end(&v, "last_inventory", config::INVTIME)) {
+        return Ok(Some(end));
+    }
+    update_inventory_time(user_id).await.map_err(|_| Localized::new("status.update-failed"))?;
+    Ok(None)
+}
+
+/// A message due to a player when their roll cooldown runs out. There is at most one per
+/// player, stored in the `reminders` collection under their ID, so a new roll replaces it.
+#[derive(Debug, Clone)]
+pub struct Reminder {
+    pub user_id: String,
+    /// Where to ping the player, `None` to send a DM.
+    pub channel_id: Option<u64>,
+}
+
+impl Reminder {
+    fn from_document(document: &Value) -> Option<Reminder> {
+        let fields = &document["fields"];
+        Some(Reminder {
+            user_id: document["name"].as_str()?.rsplit('/').next()?.to_string(),
+            channel_id: fields["channel"]["stringValue"].as_str().and_then(|s| s.parse().ok()),
+        })
+    }
+}
+
+pub async fn schedule_reminder(user_id: String, due: DateTime<Utc>, channel_id: Option<u64>) -> Result<(), Localized> {
+    let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/reminders/{user_id}", project_id = get_project_id(), user_id = user_id);
+    let channel = match channel_id {
+        Some(channel_id) => json!({ "stringValue": channel_id.to_string() }),
+        None => json!({ "nullValue": null }),
+    };
+    let data = json!({
+        "fields": {
+            "due": {
+                "timestampValue": due.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+            },
+            "channel": channel
+        }
+    });
+
+    let client = reqwest::Client::new();
+    let response = client.patch(&request_url)
+        .json(&data)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(Localized::new("remind.schedule-failed"))
     }
-    let last_rolled = Utc.datetime_from_str(raw_date.as_str(), "%Y-%m-%dT%H:%M:%SZ").expect("Invalid date");
-    let current_time = Utc::now();
-    let diff = (current_time - last_rolled).num_minutes();
-    if diff > config::INVTIME {
-        let update_status = update_inventory_time(user_id).await;
-        if update_status.is_err() {
-            return Err("Could not update the status".to_string());
+}
+
+pub async fn cancel_reminder(user_id: String) -> Result<(), Localized> {
+    let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/reminders/{user_id}", project_id = get_project_id(), user_id = user_id);
+    let client = reqwest::Client::new();
+    client.delete(&request_url).send().await.map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Turns roll reminders on or off. Turning them on while the roll is cooling down schedules
+/// one for the end of the current cooldown.
+pub async fn set_reminders(user_id: String, enabled: bool, channel_id: Option<u64>) -> Result<(), Localized> {
+    let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/users/{user_id}?updateMask.fieldPaths=remind&updateMask.fieldPaths=remind_channel", project_id = get_project_id(), user_id = user_id);
+    let channel = match channel_id {
+        Some(channel_id) => json!({ "stringValue": channel_id.to_string() }),
+        None => json!({ "nullValue": null }),
+    };
+    let data = json!({
+        "fields": {
+            "remind": {
+                "booleanValue": enabled
+            },
+            "remind_channel": channel
         }
-        return Ok(true);
+    });
+
+    let client = reqwest::Client::new();
+    let response = client.patch(&request_url)
+        .json(&data)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(Localized::new("settings.save-failed"));
     }
-    Ok(false)
+    match (enabled, get_cooldowns(user_id.clone()).await?.roll) {
+        (true, Some(due)) => schedule_reminder(user_id, due, channel_id).await,
+        (true, None) => Ok(()),
+        (false, _) => cancel_reminder(user_id).await,
+    }
+}
+
+/// Reminders whose time has come.
+pub async fn due_reminders() -> Result<Vec<Reminder>, Localized> {
+    let documents = run_query(json!({
+        "from": [{ "collectionId": "reminders" }],
+        "where": {
+            "fieldFilter": {
+                "field": { "fieldPath": "due" },
+                "op": "LESS_THAN_OR_EQUAL",
+                "value": { "timestampValue": Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string() }
+            }
+        }
+    })).await?;
+    Ok(documents.iter().filter_map(Reminder::from_document).collect())
+}
+
+/// When each cooldown-gated action becomes available again, `None` for those that already are.
+#[derive(Debug, Clone, Default)]
+pub struct Cooldowns {
+    pub roll: Option<DateTime<Utc>>,
+    pub inventory: Option<DateTime<Utc>>,
+}
+
+pub async fn get_cooldowns(user_id: String) -> Result<Cooldowns, Localized> {
+    let v = match get_user_document(user_id).await? {
+        Some(v) => v,
+        None => return Ok(Cooldowns::default()),
+    };
+    Ok(Cooldowns {
+        roll: cooldown_end(&v, "last_rolled", config::ROLLTIME),
+        inventory: cooldown_end(&v, "last_inventory", config::INVTIME),
+    })
 }
 
-async fn update_inventory_time(user_id: String) -> Result<(), String> {
+async fn update_inventory_time(user_id: String) -> Result<(), Localized> {
     let request_url = format!("https://firestore.googleapis.com/v1beta1/projects/{project_id}/databases/(default)/documents/users/{user_id}?updateMask.fieldPaths=last_inventory&alt=json", project_id = get_project_id(), user_id = user_id);
     let current_time = Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string();
     let data = json!({
@@ -390,9 +1270,307 @@ async fn update_inventory_time(user_id: String) -> Result<(), String> {
     let response = client.patch(&request_url)
         .json(&data)
         .send()
-        .await;
-        
-    response.expect("Uh oh.").text().await.expect("Uh oh. 1");
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(Localized::new("status.update-failed"));
+    }
     Ok(())
 }
 
+/// What a player's profile shows, read from their user document.
+#[derive(Debug, Clone, Default)]
+pub struct PlayerProfile {
+    /// Owned card IDs and quantities.
+    pub cards: Vec<(String, u16)>,
+    /// When the player first rolled, or when their document was created for players who
+    /// rolled before rolls were counted.
+    pub joined: Option<DateTime<Utc>>,
+    pub roll_count: i64,
+    pub balance: Option<i64>,
+    /// The chosen showcase card if it is still owned, otherwise the first favorite.
+    pub showcase: Option<String>,
+}
+
+pub async fn get_profile(user_id: String) -> Result<Option<PlayerProfile>, Localized> {
+    let v = match get_user_document(user_id).await? {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    let fields = &v["fields"];
+    let timestamp = |value: &Value| value.as_str()
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|t| t.with_timezone(&Utc));
+    let integer = |value: &Value| value["integerValue"].as_str().and_then(|s| s.parse::<i64>().ok());
+    let owned_cards: Vec<CollectionCard> = fields["cards"]["arrayValue"]["values"].as_array()
+        .map(|values| values.iter().map(CollectionCard::from_json).collect())
+        .unwrap_or_default();
+    let showcase = fields["showcase"]["stringValue"].as_str()
+        .filter(|id| owned_cards.iter().any(|card| card.id == *id))
+        .map(|id| id.to_string())
+        .or_else(|| owned_cards.iter().find(|card| card.favorite).map(|card| card.id.clone()));
+    Ok(Some(PlayerProfile {
+        cards: owned_cards.iter().map(|card| (card.id.clone(), card.quantity)).collect(),
+        joined: timestamp(&fields["first_rolled"]["timestampValue"]).or_else(|| timestamp(&v["createTime"])),
+        roll_count: integer(&fields["roll_count"]).unwrap_or(0),
+        balance: integer(&fields["balance"]),
+        showcase,
+    }))
+}
+
+/// Per-guild configuration stored in the `guilds` collection.
+#[derive(Debug, Clone, Default)]
+pub struct GuildSettings {
+    pub mod_log_channel: Option<u64>,
+    pub prefix: Option<String>,
+    pub locale: Option<String>,
+    /// Whether the guild keeps its own collections instead of sharing the global ones.
+    pub isolated: bool,
+    /// Channels each command group is limited to, by group name. Groups without channels can
+    /// be used anywhere.
+    pub allowed_channels: HashMap<String, Vec<u64>>,
+    /// Roles that grant each permission tier, by tier name.
+    pub tier_roles: HashMap<String, Vec<u64>>,
+}
+
+/// Reads the `(name)(suffix)` fields of a settings document that hold lists of Discord IDs,
+/// keyed by name.
+fn id_lists(fields: &Value, suffix: &str) -> HashMap<String, Vec<u64>> {
+    let mut lists = HashMap::new();
+    if let Some(map) = fields.as_object() {
+        for (field, value) in map {
+            if let Some(name) = field.strip_suffix(suffix) {
+                let ids = value["arrayValue"]["values"].as_array().map(|values| {
+                    values.iter().filter_map(|id| id["stringValue"].as_str().and_then(|s| s.parse().ok())).collect()
+                }).unwrap_or_default();
+                lists.insert(name.to_string(), ids);
+            }
+        }
+    }
+    lists
+}
+
+pub async fn get_guild_settings(guild_id: String) -> Result<GuildSettings, Localized> {
+    let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/guilds/{guild_id}", project_id = get_project_id(), guild_id = guild_id);
+    let response = reqwest::get(&request_url).await.map_err(|e| e.to_string())?;
+    if response.status().is_client_error() {
+        return Ok(GuildSettings::default());
+    }
+    let text = response.text().await.map_err(|e| e.to_string())?;
+    let v: Value = serde_json::from_str(text.as_str()).map_err(|e| e.to_string())?;
+    let fields = &v["fields"];
+    Ok(GuildSettings {
+        mod_log_channel: fields["mod_log_channel"]["stringValue"].as_str().and_then(|s| s.parse().ok()),
+        prefix: fields["prefix"]["stringValue"].as_str().map(|s| s.to_string()),
+        locale: fields["locale"]["stringValue"].as_str().map(|s| s.to_string()),
+        isolated: fields["isolated"]["booleanValue"].as_bool().unwrap_or(false),
+        allowed_channels: id_lists(fields, "_channels"),
+        tier_roles: id_lists(fields, "_roles"),
+    })
+}
+
+/// Writes a single field of a guild's settings document, creating it if needed.
+pub async fn set_guild_setting(guild_id: String, field: &str, value: Value) -> Result<(), Localized> {
+    let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/guilds/{guild_id}?updateMask.fieldPaths={field}", project_id = get_project_id(), guild_id = guild_id, field = field);
+    let data = json!({
+        "fields": {
+            field: value
+        }
+    });
+
+    let client = reqwest::Client::new();
+    let response = client.patch(&request_url)
+        .json(&data)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(Localized::new("guild-settings.save-failed"))
+    }
+}
+
+/// Circulation counters for a card, kept in the `stats` collection and updated by every
+/// movement so they never need a scan of the user documents.
+#[derive(Debug, Clone, Default)]
+pub struct CardStats {
+    pub total: i64,
+    /// Holders and how many copies each owns, most copies first.
+    pub holders: Vec<(String, i64)>,
+    pub first_roller: Option<String>,
+    pub first_rolled: Option<DateTime<Utc>>,
+}
+
+pub async fn get_card_stats(card_id: String) -> Result<CardStats, Localized> {
+    let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/stats/{card_id}", project_id = get_project_id(), card_id = card_id);
+    let response = reqwest::get(&request_url).await.map_err(|e| e.to_string())?;
+    if response.status().is_client_error() {
+        return Ok(CardStats::default());
+    }
+    let text = response.text().await.map_err(|e| e.to_string())?;
+    let v: Value = serde_json::from_str(text.as_str()).map_err(|e| e.to_string())?;
+    let fields = &v["fields"];
+    let integer = |value: &Value| value["integerValue"].as_str().and_then(|s| s.parse::<i64>().ok()).unwrap_or(0);
+    let mut holders: Vec<(String, i64)> = fields["holders"]["mapValue"]["fields"].as_object()
+        .map(|holders| holders.iter().map(|(user, count)| (user.clone(), integer(count))).filter(|(_, count)| *count > 0).collect())
+        .unwrap_or_default();
+    holders.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    Ok(CardStats {
+        total: integer(&fields["total"]),
+        holders,
+        first_roller: fields["first_roller"]["stringValue"].as_str().map(|s| s.to_string()),
+        first_rolled: fields["first_rolled"]["timestampValue"].as_str()
+            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+            .map(|t| t.with_timezone(&Utc)),
+    })
+}
+
+/// A commit write that adds each `(user, delta)` to the card's holder counts and the sum
+/// of the deltas to its total.
+fn circulation_write(card_id: &str, changes: &[(String, i64)]) -> Value {
+    let total: i64 = changes.iter().map(|(_, delta)| delta).sum();
+    let mut transforms = vec![json!({
+        "fieldPath": "total",
+        "increment": { "integerValue": total.to_string() }
+    })];
+    for (user_id, delta) in changes {
+        transforms.push(json!({
+            "fieldPath": format!("holders.`{}`", user_id),
+            "increment": { "integerValue": delta.to_string() }
+        }));
+    }
+    json!({
+        "transform": {
+            "document": document_name(format!("stats/{}", card_id)),
+            "fieldTransforms": transforms
+        }
+    })
+}
+
+/// Records the first roller unless someone already is. The write is conditional on the
+/// counters not having changed since they were read, so it is retried a few times when
+/// other holders change them at the same moment.
+async fn record_first_roller(card_id: String, roller: String) -> Result<(), Localized> {
+    let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/stats/{card_id}", project_id = get_project_id(), card_id = card_id);
+    let mut result = Ok(());
+    for _ in 0..3 {
+        let response = reqwest::get(&request_url).await.map_err(|e| e.to_string())?;
+        let v: Value = serde_json::from_str(response.text().await.map_err(|e| e.to_string())?.as_str()).unwrap_or_default();
+        let update_time = match v["updateTime"].as_str() {
+            Some(update_time) if v["fields"]["first_roller"].is_null() => update_time.to_string(),
+            _ => return Ok(()),
+        };
+        result = commit(vec![json!({
+            "update": {
+                "name": document_name(format!("stats/{}", card_id)),
+                "fields": {
+                    "first_roller": {
+                        "stringValue": roller
+                    },
+                    "first_rolled": {
+                        "timestampValue": Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()
+                    }
+                }
+            },
+            "updateMask": {
+                "fieldPaths": ["first_roller", "first_rolled"]
+            },
+            "currentDocument": {
+                "updateTime": update_time
+            }
+        })]).await;
+        if result.is_ok() {
+            break;
+        }
+    }
+    result
+}
+
+/// Rebuilds every card's holder counts and totals from the user documents. Only needed once
+/// for collections that existed before the counters did; first rollers are left untouched.
+pub async fn rebuild_circulation() -> Result<usize, Localized> {
+    let mut counts: std::collections::HashMap<String, Vec<(String, i64)>> = std::collections::HashMap::new();
+    let mut page_token = String::new();
+    loop {
+        let request_url = format!("https://firestore.googleapis.com/v1/projects/{project_id}/databases/(default)/documents/users?pageSize=300&pageToken={page_token}", project_id = get_project_id(), page_token = page_token);
+        let response = reqwest::get(request_url).await.map_err(|e| e.to_string())?;
+        let text = response.text().await.map_err(|e| e.to_string())?;
+        let v: Value = serde_json::from_str(text.as_str()).map_err(|e| e.to_string())?;
+        for document in v["documents"].as_array().cloned().unwrap_or_default() {
+            let user_id = document["name"].as_str().and_then(|name| name.rsplit('/').next()).unwrap_or_default().to_string();
+            let owned_cards = document["fields"]["cards"]["arrayValue"]["values"].as_array().cloned().unwrap_or_default();
+            for card in owned_cards.iter().map(CollectionCard::from_json) {
+                counts.entry(card.id).or_default().push((user_id.clone(), card.quantity as i64));
+            }
+        }
+        match v["nextPageToken"].as_str() {
+            Some(token) => page_token = token.to_string(),
+            None => break,
+        }
+    }
+
+    for (card_id, holders) in counts.iter() {
+        let total: i64 = holders.iter().map(|(_, count)| count).sum();
+        let mut holder_fields = serde_json::Map::new();
+        for (user_id, count) in holders {
+            holder_fields.insert(user_id.clone(), json!({ "integerValue": count.to_string() }));
+        }
+        commit(vec![json!({
+            "update": {
+                "name": document_name(format!("stats/{}", card_id)),
+                "fields": {
+                    "total": { "integerValue": total.to_string() },
+                    "holders": { "mapValue": { "fields": holder_fields } }
+                }
+            },
+            "updateMask": {
+                "fieldPaths": ["total", "holders"]
+            }
+        })]).await?;
+    }
+    Ok(counts.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, fields: Value) -> LedgerEntry {
+        LedgerEntry::from_document(&json!({ "name": format!("ledger/{}", id), "fields": fields })).unwrap()
+    }
+
+    #[test]
+    fn movement_follows_the_reason() {
+        let roll = entry("a", ledger_fields("42".to_string(), None, "c1".to_string(), 1, LedgerReason::Roll));
+        assert_eq!(roll.movement(), (None, Some("42".to_string())));
+        let trade = entry("b", ledger_fields("42".to_string(), Some("7".to_string()), "c1".to_string(), 1, LedgerReason::Trade));
+        assert_eq!(trade.movement(), (Some("42".to_string()), Some("7".to_string())));
+        let revoke = entry("c", ledger_fields("admin".to_string(), Some("42".to_string()), "c1".to_string(), 1, LedgerReason::Revoke));
+        assert_eq!(revoke.movement(), (Some("42".to_string()), None));
+    }
+
+    #[test]
+    fn reversal_moves_the_card_back() {
+        let trade = entry("a", ledger_fields("42".to_string(), Some("7".to_string()), "c1".to_string(), 1, LedgerReason::Trade));
+        let undo = entry("b", reversal_fields(&trade, "42".to_string(), LedgerReason::Undo));
+        assert_eq!(undo.movement(), (Some("7".to_string()), Some("42".to_string())));
+        assert_eq!(undo.players(), vec!["7", "42"]);
+    }
+
+    #[test]
+    fn reversed_revocation_returns_the_card_to_the_player() {
+        let revoke = entry("a", ledger_fields("admin".to_string(), Some("42".to_string()), "c1".to_string(), 1, LedgerReason::Revoke));
+        let reversal = entry("b", reversal_fields(&revoke, "admin".to_string(), LedgerReason::Reversal));
+        assert_eq!(reversal.movement(), (None, Some("42".to_string())));
+        assert_eq!(reversal.players(), vec!["42"]);
+    }
+
+    #[test]
+    fn reversed_grant_takes_the_card_back_from_the_player() {
+        let grant = entry("a", ledger_fields("admin".to_string(), Some("42".to_string()), "c1".to_string(), 1, LedgerReason::Grant));
+        let reversal = entry("b", reversal_fields(&grant, "admin".to_string(), LedgerReason::Reversal));
+        assert_eq!(reversal.movement(), (Some("42".to_string()), None));
+        assert_eq!(reversal.players(), vec!["42"]);
+    }
+}
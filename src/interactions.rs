@@ -1,10 +1,20 @@
 use serenity::{
-    collector::ReactionAction,
-    futures::StreamExt,
-    model::prelude::{Message, ReactionType, User},
-    prelude::Context,
+    builder::{CreateComponents, CreateEmbed},
+    model::interactions::application_command::ApplicationCommandInteraction,
+    model::interactions::message_component::{ActionRowComponent, ButtonStyle, InputTextStyle, MessageComponentInteraction},
+    model::interactions::modal::ModalSubmitInteraction,
+    model::interactions::InteractionResponseType,
+    model::prelude::{AttachmentType, ChannelId, GuildId, Message, User, UserId},
+    prelude::{Context, Mentionable},
 };
+use serenity::framework::standard::CommandResult;
+use serenity::framework::standard::macros::hook;
 use std::time::Duration;
+use tracing::error;
+
+use crate::config;
+use crate::locale::{self, Localized};
+use crate::scope::{self, Scope};
 
 use serenity::Error as SerenityError;
 use std::{
@@ -20,10 +30,6 @@ use std::{
 pub enum Error {
     /// Error returned by serenity.
     SerenityError(SerenityError),
-    /// Error returned when an operation times out.
-    TimeoutError,
-    /// Error returned when user's choice is invalid.
-    InvalidChoice,
     /// Error returned for all other cases.
     Other(String),
 }
@@ -34,8 +40,6 @@ impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let err = match self {
             Error::SerenityError(e) => Cow::from(e.to_string()),
-            Error::TimeoutError => Cow::from("You took too long to respond."),
-            Error::InvalidChoice => Cow::from("Invalid choice!"),
             Error::Other(e) => Cow::from(e),
         };
 
@@ -61,56 +65,284 @@ impl From<SerenityError> for Error {
     }
 }
 
-pub async fn add_reactions(
-    ctx: &Context,
-    msg: &Message,
-    emojis: Vec<ReactionType>,
-) -> Result<(), String> {
-    let channel_id = msg.channel_id;
-    let msg_id = msg.id;
-    let http = ctx.http.clone();
-
-    tokio::spawn(async move {
-        for emoji in emojis {
-            http.create_reaction(channel_id.0, msg_id.0, &emoji).await?;
+/// Where a command was run from: a prefix command message or a slash command. Commands
+/// answer through this so both versions share one implementation.
+///
+/// Slash commands must be deferred before they are handed to a command, every answer is
+/// then sent as a followup.
+#[derive(Clone, Copy)]
+pub enum Invocation<'a> {
+    Message(&'a Message),
+    Slash(&'a ApplicationCommandInteraction),
+}
+
+impl<'a> From<&'a Message> for Invocation<'a> {
+    fn from(msg: &'a Message) -> Self {
+        Invocation::Message(msg)
+    }
+}
+
+impl<'a> Invocation<'a> {
+    pub fn author(&self) -> &'a User {
+        match self {
+            Invocation::Message(msg) => &msg.author,
+            Invocation::Slash(command) => &command.user,
         }
+    }
 
-        Result::<(), Error>::Ok(())
-    });
+    pub fn guild_id(&self) -> Option<GuildId> {
+        match self {
+            Invocation::Message(msg) => msg.guild_id,
+            Invocation::Slash(command) => command.guild_id,
+        }
+    }
+
+    pub fn channel_id(&self) -> ChannelId {
+        match self {
+            Invocation::Message(msg) => msg.channel_id,
+            Invocation::Slash(command) => command.channel_id,
+        }
+    }
+
+    /// The economy the command runs in.
+    pub async fn scope(&self, ctx: &Context) -> Result<Scope, Localized> {
+        scope::scope(ctx, self.guild_id()).await
+    }
+
+    /// The author's player key in the economy the command runs in.
+    pub async fn player(&self, ctx: &Context) -> Result<String, Localized> {
+        Ok(self.scope(ctx).await?.key(self.author().id))
+    }
 
-    Ok(())
+    /// The language to answer the author in.
+    pub async fn locale(&self, ctx: &Context) -> String {
+        locale::locale(ctx, self.author().id, self.guild_id()).await
+    }
+
+    /// Answers the author, as a reply for prefix commands.
+    pub async fn reply(&self, ctx: &Context, content: impl std::fmt::Display) -> Result<Message, SerenityError> {
+        match self {
+            Invocation::Message(msg) => msg.reply(ctx, content).await,
+            Invocation::Slash(command) => command.create_followup_message(&ctx.http, |f| f.content(content)).await,
+        }
+    }
+
+    /// Sends a plain message to the channel the command was run in.
+    pub async fn say(&self, ctx: &Context, content: impl std::fmt::Display) -> Result<Message, SerenityError> {
+        match self {
+            Invocation::Message(msg) => msg.channel_id.say(&ctx.http, content).await,
+            Invocation::Slash(command) => command.create_followup_message(&ctx.http, |f| f.content(content)).await,
+        }
+    }
+
+    pub async fn send_embed(&self, ctx: &Context, content: impl std::fmt::Display, embed: CreateEmbed) -> Result<Message, SerenityError> {
+        self.send(ctx, content.to_string(), Some(embed), None).await
+    }
+
+    pub async fn send_file(&self, ctx: &Context, content: impl std::fmt::Display, data: Vec<u8>, filename: &str) -> Result<Message, SerenityError> {
+        let file = AttachmentType::Bytes { data: data.into(), filename: filename.to_string() };
+        match self {
+            Invocation::Message(msg) => msg.channel_id.send_message(&ctx.http, |m| m.content(content).add_file(file)).await,
+            Invocation::Slash(command) => command.create_followup_message(&ctx.http, |f| f.content(content).add_file(file)).await,
+        }
+    }
+
+    async fn send(&self, ctx: &Context, content: String, embed: Option<CreateEmbed>, components: Option<CreateComponents>) -> Result<Message, SerenityError> {
+        match self {
+            Invocation::Message(msg) => msg.channel_id.send_message(&ctx.http, |m| {
+                if !content.is_empty() {
+                    m.content(content);
+                }
+                if let Some(embed) = embed {
+                    m.set_embed(embed);
+                }
+                if let Some(components) = components {
+                    m.set_components(components);
+                }
+                m
+            }).await,
+            Invocation::Slash(command) => command.create_followup_message(&ctx.http, |f| {
+                if !content.is_empty() {
+                    f.content(content);
+                }
+                if let Some(embed) = embed {
+                    f.add_embed(embed);
+                }
+                if let Some(components) = components {
+                    f.set_components(components);
+                }
+                f
+            }).await,
+        }
+    }
 }
 
-pub async fn reaction_prompt(
-    ctx: &Context,
-    msg: &Message,
-    user: &User,
-    emojis: &[ReactionType],
-    timeout: f32,
-) -> Result<(usize, ReactionType), Error> {
-    add_reactions(ctx, msg, emojis.to_vec()).await?;
-
-    let mut collector = user
-        .await_reactions(&ctx)
-        .message_id(msg.id)
-        .timeout(Duration::from_secs_f32(timeout)).build();
-
-    while let Some(action) = collector.next().await {
-        if let ReactionAction::Added(reaction) = action.as_ref() {
-            if emojis.contains(&reaction.emoji) {
-                return Ok((
-                    emojis.iter().position(|p| p == &reaction.emoji).unwrap(),
-                    reaction.emoji.clone(),
-                ));
+const FIRST: &str = "paginator_first";
+const PREVIOUS: &str = "paginator_previous";
+const JUMP: &str = "paginator_jump";
+const NEXT: &str = "paginator_next";
+const LAST: &str = "paginator_last";
+
+/// Pages through a message with first/previous/jump/next/last buttons. Only `owner` can
+/// press the buttons, and the buttons are disabled once nobody has pressed one for
+/// `PAGINATORTIMEOUT` seconds.
+///
+/// `render` builds the content and embed of a page from its index.
+pub struct Paginator<F> {
+    owner: UserId,
+    page_count: usize,
+    page: usize,
+    locale: String,
+    render: F,
+}
+
+impl<F> Paginator<F>
+where
+    F: Fn(usize) -> (String, CreateEmbed) + Send + Sync,
+{
+    pub fn new(owner: UserId, page_count: usize, render: F) -> Self {
+        Paginator {
+            owner,
+            page_count,
+            page: 0,
+            locale: config::DEFAULTLOCALE.to_string(),
+            render,
+        }
+    }
+
+    pub fn start_at(mut self, page: usize) -> Self {
+        self.page = page.min(self.page_count.saturating_sub(1));
+        self
+    }
+
+    /// Sends the first page in answer to `invocation` and runs the paginator until it times out.
+    pub async fn send(mut self, ctx: &Context, invocation: Invocation<'_>) -> Result<(), Error> {
+        self.locale = invocation.locale(ctx).await;
+        let (content, embed) = (self.render)(self.page);
+        let components = (self.page_count > 1).then(|| self.components(false));
+        let message = invocation.send(ctx, content, Some(embed), components).await?;
+        self.listen(ctx, message).await
+    }
+
+    async fn listen(mut self, ctx: &Context, mut message: Message) -> Result<(), Error> {
+        if self.page_count <= 1 {
+            return Ok(());
+        }
+        let result = self.run(ctx, &mut message).await;
+        // The buttons stop working once nobody listens, so they are disabled however the loop ended.
+        let disabled = message.edit(&ctx.http, |m| m.set_components(self.components(true))).await;
+        result?;
+        disabled?;
+        Ok(())
+    }
+
+    /// Answers button presses and page jumps until nobody has used either for
+    /// `PAGINATORTIMEOUT` seconds. The jump modal is answered like a press, so an open modal
+    /// does not hold up the buttons.
+    async fn run(&mut self, ctx: &Context, message: &mut Message) -> Result<(), Error> {
+        let timeout = Duration::from_secs(config::PAGINATORTIMEOUT);
+        loop {
+            tokio::select! {
+                interaction = message.await_component_interaction(ctx).timeout(timeout) => match interaction {
+                    Some(interaction) => self.press(ctx, &interaction).await?,
+                    None => return Ok(()),
+                },
+                submission = message.await_modal_interaction(ctx).author_id(self.owner).timeout(timeout) => match submission {
+                    Some(submission) => self.jump(ctx, &submission).await?,
+                    None => return Ok(()),
+                },
             }
         }
     }
 
-    Err(Error::TimeoutError)
+    async fn press(&mut self, ctx: &Context, interaction: &MessageComponentInteraction) -> Result<(), Error> {
+        if interaction.user.id != self.owner {
+            interaction.create_interaction_response(&ctx.http, |r| {
+                r.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|d| d.content(locale::text(&self.locale, "paginator.not-owner", &[("user", &self.owner.mention())])).ephemeral(true))
+            }).await?;
+            return Ok(());
+        }
+
+        let last = self.page_count - 1;
+        match interaction.data.custom_id.as_str() {
+            FIRST => self.page = 0,
+            PREVIOUS => self.page = self.page.saturating_sub(1),
+            NEXT => self.page = (self.page + 1).min(last),
+            LAST => self.page = last,
+            JUMP => return self.prompt_page(ctx, interaction).await,
+            _ => return Ok(()),
+        }
+
+        let (content, embed) = (self.render)(self.page);
+        interaction.create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|d| d.content(content).set_embed(embed).set_components(self.components(false)))
+        }).await?;
+        Ok(())
+    }
+
+    /// Opens a modal asking for a page number. The answer arrives as a modal submission.
+    async fn prompt_page(&self, ctx: &Context, interaction: &MessageComponentInteraction) -> Result<(), Error> {
+        interaction.create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::Modal).interaction_response_data(|d| {
+                d.custom_id(JUMP).title(locale::text(&self.locale, "paginator.jump-title", &[])).components(|c| {
+                    c.create_action_row(|row| {
+                        row.create_input_text(|t| {
+                            t.custom_id("page")
+                                .label(locale::text(&self.locale, "paginator.jump-label", &[("count", &self.page_count)]))
+                                .style(InputTextStyle::Short)
+                                .required(true)
+                        })
+                    })
+                })
+            })
+        }).await?;
+        Ok(())
+    }
+
+    /// Moves to the page typed into the jump modal. Anything that is not a page number keeps
+    /// the current page.
+    async fn jump(&mut self, ctx: &Context, submission: &ModalSubmitInteraction) -> Result<(), Error> {
+        let value = submission.data.components.iter()
+            .flat_map(|row| row.components.iter())
+            .find_map(|component| match component {
+                ActionRowComponent::InputText(input) => Some(input.value.clone()),
+                _ => None,
+            });
+        if let Some(page) = value.and_then(|v| v.trim().parse::<usize>().ok()) {
+            self.page = page.saturating_sub(1).min(self.page_count - 1);
+        }
+
+        let (content, embed) = (self.render)(self.page);
+        submission.create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|d| d.content(content).set_embed(embed).set_components(self.components(false)))
+        }).await?;
+        Ok(())
+    }
+
+    fn components(&self, disabled: bool) -> CreateComponents {
+        let last = self.page_count - 1;
+        let mut components = CreateComponents::default();
+        components.create_action_row(|row| {
+            for (id, emoji, inactive) in [
+                (FIRST, '⏮', self.page == 0),
+                (PREVIOUS, '◀', self.page == 0),
+                (JUMP, '🔢', false),
+                (NEXT, '▶', self.page == last),
+                (LAST, '⏭', self.page == last),
+            ] {
+                row.create_button(|b| b.custom_id(id).emoji(emoji).style(ButtonStyle::Secondary).disabled(disabled || inactive));
+            }
+            row
+        });
+        components
+    }
 }
 
-pub async fn send_error(ctx: &Context, msg: &Message, error_message: String) -> Result<(), String> {
-    let status = msg.channel_id.say(ctx.clone().http, error_message).await;
+pub async fn send_error(ctx: &Context, invocation: Invocation<'_>, error_message: String) -> Result<(), String> {
+    let status = invocation.say(ctx, error_message).await;
     match status {
         Ok(_) => {
             Ok(())
@@ -120,4 +352,16 @@ pub async fn send_error(ctx: &Context, msg: &Message, error_message: String) ->
             Ok(())
         }
     }
-}
\ No newline at end of file
+}
+
+/// Tells the author when a prefix command stopped with an error instead of answering.
+#[hook]
+pub async fn after(ctx: &Context, msg: &Message, command_name: &str, result: CommandResult) {
+    if let Err(why) = result {
+        error!("Command {} failed: {:?}", command_name, why);
+        let lang = Invocation::from(msg).locale(ctx).await;
+        if let Err(why) = msg.reply(ctx, locale::error(&lang, &*why)).await {
+            error!("Could not report the failure of {}: {:?}", command_name, why);
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{Arc, OnceLock};
+
+use serde_json::json;
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use crate::config;
+use crate::firebase;
+
+/// Message catalogs, one flat JSON object of keys to messages per language. Messages fill in
+/// `{name}` placeholders from the arguments given to `text`.
+const CATALOGS: [(&str, &str); 2] = [
+    ("en", include_str!("../locales/en.json")),
+    ("es", include_str!("../locales/es.json")),
+];
+
+fn catalogs() -> &'static HashMap<&'static str, HashMap<String, String>> {
+    static PARSED: OnceLock<HashMap<&'static str, HashMap<String, String>>> = OnceLock::new();
+    PARSED.get_or_init(|| {
+        CATALOGS.iter()
+            .map(|(code, source)| (*code, serde_json::from_str(source).unwrap_or_else(|e| panic!("Invalid message catalog {}: {}", code, e))))
+            .collect()
+    })
+}
+
+/// The language codes there is a catalog for.
+pub fn supported() -> Vec<&'static str> {
+    CATALOGS.iter().map(|(code, _)| *code).collect()
+}
+
+/// A language by its own name and code, for example "English (en)".
+pub fn describe(locale: &str) -> String {
+    format!("{} ({})", text(locale, "language.name", &[]), locale)
+}
+
+/// Every supported language, described for a message.
+pub fn available() -> String {
+    supported().into_iter().map(describe).collect::<Vec<String>>().join(", ")
+}
+
+/// The message `key` in `locale`, or in English if it has not been translated yet. `None` when
+/// no catalog has the message.
+pub fn lookup(locale: &str, key: &str) -> Option<&'static String> {
+    let catalogs = catalogs();
+    catalogs.get(locale).and_then(|catalog| catalog.get(key))
+        .or_else(|| catalogs.get(config::DEFAULTLOCALE).and_then(|catalog| catalog.get(key)))
+}
+
+/// Looks up the message `key` in `locale`, using English for messages that have not been
+/// translated yet.
+pub fn text(locale: &str, key: &str, args: &[(&str, &(dyn Display + Sync))]) -> String {
+    match lookup(locale, key) {
+        Some(template) => args.iter().fold(template.clone(), |message, (name, value)| message.replace(&format!("{{{}}}", name), &value.to_string())),
+        None => key.to_string(),
+    }
+}
+
+/// A message for a player that is put into their language only when it is sent, so code
+/// without a language at hand, such as database calls, can still fail with a translated error.
+/// Errors from outside the bot have no catalog entry and are kept as they are.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Localized {
+    Key(&'static str, Vec<(&'static str, String)>),
+    Raw(String),
+}
+
+impl Localized {
+    pub fn new(key: &'static str) -> Localized {
+        Localized::Key(key, vec![])
+    }
+
+    /// Fills in the `{name}` placeholder of the message.
+    pub fn arg(self, name: &'static str, value: impl Display) -> Localized {
+        match self {
+            Localized::Key(key, mut args) => {
+                args.push((name, value.to_string()));
+                Localized::Key(key, args)
+            },
+            raw => raw,
+        }
+    }
+
+    pub fn render(&self, locale: &str) -> String {
+        match self {
+            Localized::Key(key, args) => {
+                let args: Vec<(&str, &(dyn Display + Sync))> = args.iter().map(|(name, value)| (*name, value as &(dyn Display + Sync))).collect();
+                text(locale, key, &args)
+            },
+            Localized::Raw(message) => message.clone(),
+        }
+    }
+}
+
+impl From<String> for Localized {
+    fn from(message: String) -> Localized {
+        Localized::Raw(message)
+    }
+}
+
+/// Renders in English, for logs.
+impl Display for Localized {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.render(config::DEFAULTLOCALE))
+    }
+}
+
+impl std::error::Error for Localized {}
+
+/// Puts the error that stopped a command into `locale`.
+pub fn error(locale: &str, error: &(dyn std::error::Error + Send + Sync + 'static)) -> String {
+    let message = match error.downcast_ref::<Localized>() {
+        Some(message) => message.render(locale),
+        None => error.to_string(),
+    };
+    text(locale, "error", &[("error", &message)])
+}
+
+/// Language settings of the users and guilds seen so far. `None` means the setting is not set.
+#[derive(Default)]
+pub struct Locales {
+    users: HashMap<UserId, Option<String>>,
+    guilds: HashMap<GuildId, Option<String>>,
+}
+
+pub struct LocaleCache;
+
+impl TypeMapKey for LocaleCache {
+    type Value = Arc<RwLock<Locales>>;
+}
+
+async fn cache(ctx: &Context) -> Arc<RwLock<Locales>> {
+    ctx.data.read().await.get::<LocaleCache>().cloned().expect("Expected LocaleCache in TypeMap.")
+}
+
+async fn user_locale(ctx: &Context, user_id: UserId) -> Option<String> {
+    let cache = cache(ctx).await;
+    if let Some(locale) = cache.read().await.users.get(&user_id) {
+        return locale.clone();
+    }
+    let locale = firebase::get_user_settings(user_id.to_string()).await.ok()?.locale;
+    cache.write().await.users.insert(user_id, locale.clone());
+    locale
+}
+
+pub async fn guild_locale(ctx: &Context, guild_id: GuildId) -> Option<String> {
+    let cache = cache(ctx).await;
+    if let Some(locale) = cache.read().await.guilds.get(&guild_id) {
+        return locale.clone();
+    }
+    let locale = firebase::get_guild_settings(guild_id.to_string()).await.ok()?.locale;
+    cache.write().await.guilds.insert(guild_id, locale.clone());
+    locale
+}
+
+/// The language to answer a user in: their own setting, then the setting of the guild, then
+/// English.
+pub async fn locale(ctx: &Context, user_id: UserId, guild_id: Option<GuildId>) -> String {
+    if let Some(locale) = user_locale(ctx, user_id).await {
+        return locale;
+    }
+    if let Some(guild_id) = guild_id {
+        if let Some(locale) = guild_locale(ctx, guild_id).await {
+            return locale;
+        }
+    }
+    config::DEFAULTLOCALE.to_string()
+}
+
+fn setting(locale: &Option<String>) -> serde_json::Value {
+    match locale {
+        Some(locale) => json!({ "stringValue": locale }),
+        None => json!({ "nullValue": null }),
+    }
+}
+
+/// Saves a user's language, or follows the server again when `locale` is `None`.
+pub async fn set_user_locale(ctx: &Context, user_id: UserId, locale: Option<String>) -> Result<(), Localized> {
+    firebase::set_user_setting(user_id.to_string(), "locale", setting(&locale)).await?;
+    cache(ctx).await.write().await.users.insert(user_id, locale);
+    Ok(())
+}
+
+/// Saves a guild's language, or goes back to English when `locale` is `None`.
+pub async fn set_guild_locale(ctx: &Context, guild_id: GuildId, locale: Option<String>) -> Result<(), Localized> {
+    firebase::set_guild_setting(guild_id.to_string(), "locale", setting(&locale)).await?;
+    cache(ctx).await.write().await.guilds.insert(guild_id, locale);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placeholders(message: &str) -> Vec<&str> {
+        let mut names: Vec<&str> = message.split('{').skip(1).filter_map(|part| part.split_once('}')).map(|(name, _)| name).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn every_catalog_translates_every_message_with_its_placeholders() {
+        let english = &catalogs()[config::DEFAULTLOCALE];
+        for code in supported() {
+            let catalog = &catalogs()[code];
+            for (key, message) in english {
+                let translation = catalog.get(key).unwrap_or_else(|| panic!("{} is missing {}", code, key));
+                assert_eq!(placeholders(translation), placeholders(message), "{} changes the placeholders of {}", code, key);
+            }
+        }
+    }
+}
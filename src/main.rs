@@ -1,11 +1,22 @@
+mod allowlist;
+mod catalog;
+mod collage;
 mod commands;
+mod filters;
 mod firebase;
 mod interactions;
+mod locale;
 mod misc;
+mod permissions;
+mod prefix;
+mod reminders;
 mod config;
+mod safeguards;
+mod scope;
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use serenity::async_trait;
@@ -15,10 +26,16 @@ use serenity::framework::StandardFramework;
 use serenity::http::Http;
 use serenity::model::event::ResumedEvent;
 use serenity::model::gateway::Ready;
+use serenity::model::interactions::application_command::ApplicationCommand;
+use serenity::model::interactions::Interaction;
 use serenity::prelude::*;
 use tracing::{error, info};
 
+use crate::commands::admin::*;
 use crate::commands::cards::*;
+use crate::commands::help::*;
+use crate::commands::slash;
+use crate::permissions::{ADMIN_CHECK, BOTOWNER_CHECK, MODERATOR_CHECK, OWNER_CHECK};
 
 pub struct ShardManagerContainer;
 
@@ -26,12 +43,34 @@ impl TypeMapKey for ShardManagerContainer {
     type Value = Arc<Mutex<ShardManager>>;
 }
 
+/// Whether the slash commands were registered since the bot started.
+static SLASH_REGISTERED: AtomicBool = AtomicBool::new(false);
+
 struct Handler;
 
 #[async_trait]
 impl EventHandler for Handler {
-    async fn ready(&self, _: Context, ready: Ready) {
+    async fn ready(&self, ctx: Context, ready: Ready) {
         info!("Connected as {}", ready.user.name);
+        // Ready comes again after every reconnect, but the definitions only change with a new
+        // build, so they are sent once per run.
+        if !SLASH_REGISTERED.swap(true, Ordering::SeqCst) {
+            if let Err(why) = ApplicationCommand::set_global_application_commands(&ctx.http, slash::register).await {
+                error!("Could not register slash commands: {:?}", why);
+                SLASH_REGISTERED.store(false, Ordering::SeqCst);
+            }
+        }
+        if let Err(why) = catalog::catalog(&ctx).await {
+            error!("Could not load the card catalog: {}", why);
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        match interaction {
+            Interaction::ApplicationCommand(command) => slash::handle(&ctx, &command).await,
+            Interaction::Autocomplete(autocomplete) => slash::autocomplete(&ctx, &autocomplete).await,
+            _ => {},
+        }
     }
 
     async fn resume(&self, _: Context, _: ResumedEvent) {
@@ -40,20 +79,44 @@ impl EventHandler for Handler {
 }
 
 #[group]
-#[commands(roll, inventory, trade, help)]
+#[commands(roll, cooldowns, remind)]
+struct Rolling;
+
+#[group]
+#[commands(inventory, view, cardstats, compare, profile, showcase, privacy, defaultsort, trade, accept, decline, undo, trades, lock, unlock, favorite, unfavorite, history)]
+struct Gameplay;
+
+#[group]
+#[commands(language, help)]
 struct General;
 
+#[group]
+#[only_in(guilds)]
+#[checks(Moderator)]
+#[commands(grant, revoke, audit, reverse)]
+struct Moderation;
+
+#[group]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[commands(modlog, prefix, serverlanguage, channels)]
+struct Admin;
+
+#[group]
+#[only_in(guilds)]
+#[checks(Owner)]
+#[commands(roles, economy)]
+struct Owner;
+
+#[group]
+#[checks(BotOwner)]
+#[commands(manage_catalog, rebuildstats)]
+struct Bot;
+
 #[tokio::main]
 async fn main() {
-    let env = dotenv::dotenv();
-    let mut prefix = "!";
-    match env {
-        Ok(_) => {
-            prefix = "~";
-        },
-        Err(_) => {
-            println!("Could not read dotenv file");
-        }
+    if dotenv::dotenv().is_err() {
+        println!("Could not read dotenv file");
     }
     tracing_subscriber::fmt::init();
 
@@ -71,12 +134,27 @@ async fn main() {
         Err(why) => panic!("Could not access application info: {:?}", why),
     };
 
-    let framework = StandardFramework::new().configure(|c| c.owners(owners).prefix(prefix)).group(&GENERAL_GROUP);
+    let bot_id = match http.get_current_user().await {
+        Ok(user) => user.id,
+        Err(why) => panic!("Could not access the bot user: {:?}", why),
+    };
+
+    let framework = StandardFramework::new()
+        .configure(|c| c.owners(owners.clone()).prefix("").dynamic_prefix(prefix::dynamic_prefix).on_mention(Some(bot_id)))
+        .before(allowlist::before)
+        .after(interactions::after)
+        .on_dispatch_error(permissions::dispatch_error)
+        .group(&ROLLING_GROUP)
+        .group(&GAMEPLAY_GROUP)
+        .group(&GENERAL_GROUP)
+        .group(&MODERATION_GROUP)
+        .group(&ADMIN_GROUP)
+        .group(&OWNER_GROUP)
+        .group(&BOT_GROUP);
 
     let intents = GatewayIntents::GUILD_MESSAGES
         | GatewayIntents::DIRECT_MESSAGES
-        | GatewayIntents::MESSAGE_CONTENT
-        | GatewayIntents::GUILD_MESSAGE_REACTIONS;
+        | GatewayIntents::MESSAGE_CONTENT;
     let mut client = Client::builder(&token, intents)
         .framework(framework)
         .event_handler(Handler)
@@ -86,8 +164,29 @@ async fn main() {
     {
         let mut data = client.data.write().await;
         data.insert::<ShardManagerContainer>(client.shard_manager.clone());
+        data.insert::<catalog::CatalogCache>(Arc::new(RwLock::new(catalog::Catalog::default())));
+        data.insert::<prefix::PrefixCache>(Arc::new(RwLock::new(HashMap::new())));
+        data.insert::<permissions::BotOwners>(Arc::new(owners));
+        data.insert::<permissions::RoleCache>(Arc::new(RwLock::new(HashMap::new())));
+        data.insert::<allowlist::ChannelCache>(Arc::new(RwLock::new(HashMap::new())));
+        data.insert::<scope::ScopeCache>(Arc::new(RwLock::new(HashMap::new())));
+        data.insert::<locale::LocaleCache>(Arc::new(RwLock::new(locale::Locales::default())));
     }
 
+    let http = client.cache_and_http.http.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            match firebase::expire_trade_offers().await {
+                Ok(0) => {},
+                Ok(expired) => info!("Expired {} trade offers", expired),
+                Err(why) => error!("Could not expire trade offers: {}", why),
+            }
+            reminders::deliver_due(&http).await;
+        }
+    });
+
     let shard_manager = client.shard_manager.clone();
 
     tokio::spawn(async move  {
@@ -1,11 +1,34 @@
+use serenity::model::id::UserId;
+use serenity::utils;
+
 use crate::config;
+use crate::firebase::LedgerEntry;
+use crate::locale;
+use crate::scope;
 
-pub fn get_category(input: String) -> Result<String, String> {
+pub fn get_category(lang: &str, input: String) -> Result<String, String> {
     if config::CHARACTERSCATEGORYALTERNATES.contains(&input.as_str()) {
         Ok("characters".to_string())
     } else if config::POSTERSCATEGORYALTERNATES.contains(&input.as_str()) {
         Ok("posters".to_string())
     } else {
-        return Err(format!("Did not recognize category: {}. Valid categories include \"characters\" and \"posters\".", input));
+        Err(locale::text(lang, "category.unknown", &[("category", &input)]))
     }
-}
\ No newline at end of file
+}
+pub fn parse_user(input: &str) -> Option<UserId> {
+    utils::parse_username(input).or_else(|| input.parse::<u64>().ok()).map(UserId)
+}
+
+pub fn describe_ledger_entry(lang: &str, entry: &LedgerEntry) -> String {
+    let actor = scope::mention(&entry.actor);
+    let counterparty = entry.counterparty.as_deref().map(scope::mention).unwrap_or_default();
+    let by = scope::mention(&entry.by.clone().unwrap_or_default());
+    let reverses = entry.reverses.clone().unwrap_or_default();
+    let args: [(&str, &(dyn std::fmt::Display + Sync)); 5] = [("actor", &actor), ("card", &entry.card_id), ("counterparty", &counterparty), ("reverses", &reverses), ("by", &by)];
+    let action = match entry.reason.as_str() {
+        "roll" | "trade" | "grant" | "revoke" => locale::text(lang, &format!("ledger.{}", entry.reason), &args),
+        "undo" | "reversal" => locale::text(lang, "ledger.reversal", &args),
+        other => format!("{} {} `{}` {}", actor, other, entry.card_id, counterparty),
+    };
+    format!("<t:{}:f> `{}` {} (x{})", entry.timestamp.timestamp(), entry.id, action, entry.quantity)
+}
@@ -0,0 +1,195 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use serde_json::json;
+use serenity::framework::standard::macros::{check, hook};
+use serenity::framework::standard::{Args, CommandGroup, CommandOptions, DispatchError, Reason};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+use tracing::error;
+
+use crate::firebase;
+use crate::interactions::Invocation;
+use crate::locale::{self, Localized};
+use crate::scope::Scope;
+use crate::{ADMIN_GROUP, BOT_GROUP, MODERATION_GROUP, OWNER_GROUP};
+
+/// The command groups that need a permission tier. Each one names its tier with its check.
+const GROUPS: [&CommandGroup; 4] = [&MODERATION_GROUP, &ADMIN_GROUP, &OWNER_GROUP, &BOT_GROUP];
+
+/// Permission tiers for staff commands, lowest first. Every tier can use the commands of the
+/// tiers below it. The first three are granted per guild; bot owners are the only ones who can
+/// change data every guild shares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Tier {
+    Moderator,
+    Admin,
+    Owner,
+    BotOwner,
+}
+
+impl Tier {
+    /// The tiers a guild can grant with roles.
+    pub const GUILD: [Tier; 3] = [Tier::Moderator, Tier::Admin, Tier::Owner];
+
+    pub fn from_name(input: &str) -> Option<Tier> {
+        match input.to_lowercase().as_str() {
+            "moderator" | "mod" => Some(Tier::Moderator),
+            "admin" => Some(Tier::Admin),
+            "owner" => Some(Tier::Owner),
+            "botowner" => Some(Tier::BotOwner),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Tier::Moderator => "moderator",
+            Tier::Admin => "admin",
+            Tier::Owner => "owner",
+            Tier::BotOwner => "botowner",
+        }
+    }
+}
+
+/// The tier a group's commands need, from the check on the group.
+pub fn group_tier(group: &CommandGroup) -> Option<Tier> {
+    group.options.checks.iter().find_map(|check| Tier::from_name(check.name))
+}
+
+/// The tier a command needs, if it is a staff command.
+pub fn required_tier(command_name: &str) -> Option<Tier> {
+    GROUPS.iter()
+        .find(|group| group.options.commands.iter().any(|command| command.options.names.contains(&command_name)))
+        .and_then(|group| group_tier(group))
+}
+
+/// The owners of the application, the only members of the BotOwner tier.
+pub struct BotOwners;
+
+impl TypeMapKey for BotOwners {
+    type Value = Arc<HashSet<UserId>>;
+}
+
+/// Roles mapped to each tier in the guilds seen so far, by tier name.
+pub struct RoleCache;
+
+impl TypeMapKey for RoleCache {
+    type Value = Arc<RwLock<HashMap<GuildId, HashMap<String, Vec<RoleId>>>>>;
+}
+
+async fn cache(ctx: &Context) -> Arc<RwLock<HashMap<GuildId, HashMap<String, Vec<RoleId>>>>> {
+    ctx.data.read().await.get::<RoleCache>().cloned().expect("Expected RoleCache in TypeMap.")
+}
+
+pub async fn tier_roles(ctx: &Context, guild_id: GuildId) -> HashMap<String, Vec<RoleId>> {
+    let cache = cache(ctx).await;
+    if let Some(roles) = cache.read().await.get(&guild_id) {
+        return roles.clone();
+    }
+    let roles: HashMap<String, Vec<RoleId>> = match firebase::get_guild_settings(guild_id.to_string()).await {
+        Ok(settings) => settings.tier_roles.into_iter()
+            .map(|(tier, roles)| (tier, roles.into_iter().map(RoleId).collect()))
+            .collect(),
+        Err(_) => return HashMap::new(),
+    };
+    cache.write().await.insert(guild_id, roles.clone());
+    roles
+}
+
+/// Saves the roles that grant a tier in a guild.
+pub async fn set_tier_roles(ctx: &Context, guild_id: GuildId, tier: Tier, roles: Vec<RoleId>) -> Result<(), Localized> {
+    let values: Vec<serde_json::Value> = roles.iter().map(|role| json!({ "stringValue": role.to_string() })).collect();
+    firebase::set_guild_setting(guild_id.to_string(), &format!("{}_roles", tier.as_str()), json!({ "arrayValue": { "values": values } })).await?;
+    cache(ctx).await.write().await.entry(guild_id).or_default().insert(tier.as_str().to_string(), roles);
+    Ok(())
+}
+
+/// The highest tier a user has where a command was used. Outside guilds only bot owners have
+/// a tier. In a guild, its owner is an Owner and members with the Administrator permission are
+/// at least Admins so a guild works before it maps any roles.
+pub async fn tier(ctx: &Context, guild_id: Option<GuildId>, user_id: UserId) -> Result<Option<Tier>, String> {
+    let owners = ctx.data.read().await.get::<BotOwners>().cloned().expect("Expected BotOwners in TypeMap.");
+    if owners.contains(&user_id) {
+        return Ok(Some(Tier::BotOwner));
+    }
+    let guild_id = match guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(None),
+    };
+    let guild = guild_id.to_partial_guild(&ctx.http).await.map_err(|e| e.to_string())?;
+    if guild.owner_id == user_id {
+        return Ok(Some(Tier::Owner));
+    }
+    let member = guild.member(ctx, user_id).await.map_err(|e| e.to_string())?;
+    let roles = tier_roles(ctx, guild_id).await;
+    let from_roles = Tier::GUILD.iter().rev()
+        .find(|tier| roles.get(tier.as_str()).map(|ids| ids.iter().any(|id| member.roles.contains(id))).unwrap_or(false))
+        .copied();
+    let administrator = guild.member_permissions(ctx, user_id).await.map(|permissions| permissions.administrator()).unwrap_or(false);
+    Ok(from_roles.max(administrator.then_some(Tier::Admin)))
+}
+
+/// Makes sure the author of a command has at least `needed`, or returns the message telling
+/// them they do not.
+pub async fn require(ctx: &Context, invocation: Invocation<'_>, command_name: &str, needed: Tier) -> Result<(), String> {
+    let lang = invocation.locale(ctx).await;
+    if needed < Tier::BotOwner && invocation.guild_id().is_none() {
+        return Err(locale::text(&lang, "permissions.guild-only", &[]));
+    }
+    match tier(ctx, invocation.guild_id(), invocation.author().id).await {
+        Ok(Some(tier)) if tier >= needed => Ok(()),
+        Ok(_) => {
+            let tier = locale::text(&lang, &format!("tier.{}", needed.as_str()), &[]);
+            Err(locale::text(&lang, "permissions.denied", &[("command", &command_name), ("tier", &tier)]))
+        },
+        Err(e) => Err(locale::text(&lang, "error", &[("error", &e)])),
+    }
+}
+
+/// Makes sure the author may change cards in `scope`. Guild tiers only reach the guild's own
+/// economy; moving cards in the global one, which every guild shares, needs a bot owner.
+pub async fn require_in_scope(ctx: &Context, invocation: Invocation<'_>, command_name: &str, scope: Scope) -> Result<(), String> {
+    match scope {
+        Scope::Global => require(ctx, invocation, command_name, Tier::BotOwner).await,
+        Scope::Guild(_) => Ok(()),
+    }
+}
+
+async fn check_tier(ctx: &Context, msg: &Message, options: &CommandOptions, needed: Tier) -> Result<(), Reason> {
+    require(ctx, msg.into(), options.names[0], needed).await.map_err(Reason::User)
+}
+
+#[check]
+#[name = "Moderator"]
+pub async fn moderator_check(ctx: &Context, msg: &Message, _: &mut Args, options: &CommandOptions) -> Result<(), Reason> {
+    check_tier(ctx, msg, options, Tier::Moderator).await
+}
+
+#[check]
+#[name = "Admin"]
+pub async fn admin_check(ctx: &Context, msg: &Message, _: &mut Args, options: &CommandOptions) -> Result<(), Reason> {
+    check_tier(ctx, msg, options, Tier::Admin).await
+}
+
+#[check]
+#[name = "Owner"]
+pub async fn owner_check(ctx: &Context, msg: &Message, _: &mut Args, options: &CommandOptions) -> Result<(), Reason> {
+    check_tier(ctx, msg, options, Tier::Owner).await
+}
+
+#[check]
+#[name = "BotOwner"]
+pub async fn bot_owner_check(ctx: &Context, msg: &Message, _: &mut Args, options: &CommandOptions) -> Result<(), Reason> {
+    check_tier(ctx, msg, options, Tier::BotOwner).await
+}
+
+/// Tells the author why a tier check stopped their command.
+#[hook]
+pub async fn dispatch_error(ctx: &Context, msg: &Message, error: DispatchError, command_name: &str) {
+    if let DispatchError::CheckFailed(_, Reason::User(reason)) = error {
+        if let Err(why) = msg.reply(ctx, reason).await {
+            error!("Could not explain why {} was refused: {:?}", command_name, why);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde_json::json;
+use serenity::framework::standard::macros::hook;
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use crate::config;
+use crate::firebase;
+use crate::locale::Localized;
+
+/// Command prefixes of the guilds seen so far, so the framework does not read the guild
+/// settings for every message.
+pub struct PrefixCache;
+
+impl TypeMapKey for PrefixCache {
+    type Value = Arc<RwLock<HashMap<GuildId, String>>>;
+}
+
+async fn cache(ctx: &Context) -> Arc<RwLock<HashMap<GuildId, String>>> {
+    ctx.data.read().await.get::<PrefixCache>().cloned().expect("Expected PrefixCache in TypeMap.")
+}
+
+pub async fn guild_prefix(ctx: &Context, guild_id: GuildId) -> String {
+    let cache = cache(ctx).await;
+    if let Some(prefix) = cache.read().await.get(&guild_id) {
+        return prefix.clone();
+    }
+    let prefix = match firebase::get_guild_settings(guild_id.to_string()).await {
+        Ok(settings) => settings.prefix.unwrap_or_else(|| config::DEFAULTPREFIX.to_string()),
+        Err(_) => return config::DEFAULTPREFIX.to_string(),
+    };
+    cache.write().await.insert(guild_id, prefix.clone());
+    prefix
+}
+
+/// Saves a guild's prefix, or goes back to the default when `prefix` is `None`.
+pub async fn set_guild_prefix(ctx: &Context, guild_id: GuildId, prefix: Option<String>) -> Result<String, Localized> {
+    let value = match &prefix {
+        Some(prefix) => json!({ "stringValue": prefix }),
+        None => json!({ "nullValue": null }),
+    };
+    firebase::set_guild_setting(guild_id.to_string(), "prefix", value).await?;
+    let prefix = prefix.unwrap_or_else(|| config::DEFAULTPREFIX.to_string());
+    cache(ctx).await.write().await.insert(guild_id, prefix.clone());
+    Ok(prefix)
+}
+
+/// The prefix of the guild a message was sent in. Direct messages use the default prefix.
+#[hook]
+pub async fn dynamic_prefix(ctx: &Context, msg: &Message) -> Option<String> {
+    match msg.guild_id {
+        Some(guild_id) => Some(guild_prefix(ctx, guild_id).await),
+        None => Some(config::DEFAULTPREFIX.to_string()),
+    }
+}
@@ -0,0 +1,46 @@
+use serenity::http::Http;
+use serenity::model::prelude::*;
+use tracing::error;
+
+use crate::config;
+use crate::firebase::{self, Reminder};
+use crate::locale::{self, Localized};
+use crate::scope;
+
+async fn deliver(http: &Http, reminder: &Reminder) -> Result<(), Localized> {
+    let user_id = scope::user_of(&reminder.user_id);
+    let user = UserId(user_id.parse().map_err(|_| format!("Invalid user ID {}", reminder.user_id))?);
+    // Rolling early or starting a collection can move the cooldown after the reminder was
+    // scheduled, so check it again rather than trusting the stored time.
+    if let Some(ready) = firebase::get_cooldowns(reminder.user_id.clone()).await?.roll {
+        return firebase::schedule_reminder(reminder.user_id.clone(), ready, reminder.channel_id).await;
+    }
+    let lang = firebase::get_user_settings(user_id.to_string()).await?.locale.unwrap_or_else(|| config::DEFAULTLOCALE.to_string());
+    let text = locale::text(&lang, "remind.ready", &[]);
+    let sent = match reminder.channel_id {
+        Some(channel) => ChannelId(channel).say(http, format!("{} {}", user.mention(), text)).await.map(|_| ()),
+        None => match user.create_dm_channel(http).await {
+            Ok(channel) => channel.say(http, text).await.map(|_| ()),
+            Err(why) => Err(why),
+        },
+    };
+    // A closed DM or a deleted channel would fail every time, so the reminder is dropped either way.
+    firebase::cancel_reminder(reminder.user_id.clone()).await?;
+    sent.map_err(|why| why.to_string().into())
+}
+
+/// Sends every reminder that has come due.
+pub async fn deliver_due(http: &Http) {
+    let reminders = match firebase::due_reminders().await {
+        Ok(reminders) => reminders,
+        Err(why) => {
+            error!("Could not load reminders: {}", why);
+            return;
+        }
+    };
+    for reminder in reminders {
+        if let Err(why) = deliver(http, &reminder).await {
+            error!("Could not send the reminder for {}: {}", reminder.user_id, why);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use chrono::{DateTime, Duration, Utc};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+use tracing::error;
+
+use crate::config;
+use crate::firebase;
+use crate::locale::{self, Localized};
+use crate::scope;
+
+/// Refuses trade offers that look like alt accounts feeding a main account: recipients
+/// with young accounts or guild memberships, and senders over the daily trade cap.
+pub async fn check_trade(ctx: &Context, guild_id: Option<GuildId>, sender: &User, recipient: UserId) -> Result<(), Localized> {
+    let recipient_user = recipient.to_user(ctx).await.map_err(|_| Localized::new("trade.unknown-user"))?;
+    if recipient_user.bot {
+        return Err(Localized::new("trade.bot"));
+    }
+
+    let now = Utc::now().timestamp();
+    let account_age = now - recipient.created_at().unix_timestamp();
+    if account_age < Duration::days(config::MINACCOUNTAGE).num_seconds() {
+        return Err(Localized::new("trade.account-age").arg("user", recipient.mention()).arg("days", config::MINACCOUNTAGE));
+    }
+
+    if let Some(guild_id) = guild_id {
+        let member = guild_id.member(ctx, recipient).await.map_err(|_| Localized::new("trade.not-member").arg("user", recipient.mention()))?;
+        let joined_at = member.joined_at.map(|t| t.unix_timestamp()).unwrap_or(now);
+        if now - joined_at < Duration::days(config::MINMEMBERAGE).num_seconds() {
+            return Err(Localized::new("trade.member-age").arg("user", recipient.mention()).arg("days", config::MINMEMBERAGE));
+        }
+    }
+
+    if let (_, Some(reset)) = trade_cap_status(scope::scope(ctx, guild_id).await?.key(sender.id)).await? {
+        return Err(Localized::new("trade.daily-cap").arg("cap", config::DAILYTRADECAP).arg("reset", reset.timestamp()));
+    }
+    Ok(())
+}
+
+/// Offers the player has sent in the last day, and when they can send another if they are at
+/// the daily cap.
+pub async fn trade_cap_status(player: String) -> Result<(usize, Option<DateTime<Utc>>), Localized> {
+    let sent = firebase::trade_offers_sent_since(player, Utc::now() - Duration::days(1)).await?;
+    let reset = if sent.len() >= config::DAILYTRADECAP {
+        sent.get(sent.len() - config::DAILYTRADECAP).map(|created| *created + Duration::days(1))
+    } else {
+        None
+    };
+    Ok((sent.len(), reset))
+}
+
+/// Reports to the guild's moderator log when cards keep flowing one way between the same
+/// pair of users. Called after a trade completes; failures are logged, never surfaced.
+pub async fn flag_transfer_pattern(ctx: &Context, guild_id: Option<GuildId>, offer: &firebase::TradeOffer) {
+    let guild_id = match guild_id {
+        Some(guild_id) => guild_id,
+        None => return,
+    };
+    let channel_id = match firebase::get_guild_settings(guild_id.to_string()).await {
+        Ok(firebase::GuildSettings { mod_log_channel: Some(channel_id), .. }) => ChannelId(channel_id),
+        _ => return,
+    };
+    let entries = match firebase::ledger_for_user(offer.from.clone()).await {
+        Ok(entries) => entries,
+        Err(why) => {
+            error!("Could not check trade pattern: {}", why);
+            return;
+        }
+    };
+    let since = Utc::now() - Duration::days(config::FUNNELWINDOW);
+    let trades = entries.iter().filter(|entry| entry.reason == "trade" && entry.timestamp >= since);
+    let (mut forward, mut reverse) = (0, 0);
+    for entry in trades {
+        if entry.actor == offer.from && entry.counterparty.as_deref() == Some(offer.to.as_str()) {
+            forward += 1;
+        } else if entry.actor == offer.to && entry.counterparty.as_deref() == Some(offer.from.as_str()) {
+            reverse += 1;
+        }
+    }
+    if reverse > 0 || forward == 0 || forward % config::FUNNELTHRESHOLD != 0 {
+        return;
+    }
+    let lang = locale::guild_locale(ctx, guild_id).await.unwrap_or_else(|| config::DEFAULTLOCALE.to_string());
+    let report = locale::text(&lang, "modlog.funneling", &[("sender", &scope::mention(&offer.from)), ("count", &forward), ("recipient", &scope::mention(&offer.to)), ("days", &config::FUNNELWINDOW), ("card", &offer.card_id)]);
+    if let Err(why) = channel_id.say(&ctx.http, report).await {
+        error!("Could not send moderator log message: {:?}", why);
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde_json::json;
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use crate::firebase::{self, CardStats, LedgerEntry};
+use crate::locale::Localized;
+
+/// Which collections, cooldowns and economy a command works on. Guilds share the global
+/// economy unless they switch to their own with `!economy isolated`; direct messages always
+/// use the global one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Global,
+    Guild(GuildId),
+}
+
+impl Scope {
+    /// The ID of a player's document in the `users` collection. Isolated guilds prefix the
+    /// user ID with their own, so the same person has a separate collection in each of them.
+    ///
+    /// Everything tied to a collection lives on the scoped document: the cards, the roll and
+    /// inventory cooldowns, roll reminders and the showcased card. Settings about the person
+    /// rather than a collection, namely their language, default sort and privacy, live on the
+    /// document of the bare user ID so they follow them into every economy.
+    pub fn key(&self, user_id: UserId) -> String {
+        match self {
+            Scope::Global => user_id.to_string(),
+            Scope::Guild(guild_id) => format!("{}_{}", guild_id, user_id),
+        }
+    }
+
+    /// Whether a player key belongs to this scope.
+    pub fn contains(&self, key: &str) -> bool {
+        match self {
+            Scope::Global => !key.contains('_'),
+            Scope::Guild(guild_id) => key.strip_prefix(&format!("{}_", guild_id)).is_some(),
+        }
+    }
+
+    /// Whether every player a ledger entry involves belongs to this scope.
+    pub fn covers(&self, entry: &LedgerEntry) -> bool {
+        let players = entry.players();
+        !players.is_empty() && players.iter().all(|key| self.contains(key))
+    }
+
+    /// Narrows a card's circulation counters, which count every scope, to the holders in this
+    /// one.
+    pub fn circulation(&self, stats: CardStats) -> CardStats {
+        let holders: Vec<(String, i64)> = stats.holders.into_iter().filter(|(key, _)| self.contains(key)).collect();
+        let first_rolled = stats.first_roller.as_deref().map(|key| self.contains(key)).unwrap_or(false);
+        CardStats {
+            total: holders.iter().map(|(_, count)| count).sum(),
+            holders,
+            first_roller: if first_rolled { stats.first_roller } else { None },
+            first_rolled: if first_rolled { stats.first_rolled } else { None },
+        }
+    }
+}
+
+/// The Discord user ID in a player key.
+pub fn user_of(key: &str) -> &str {
+    key.rsplit('_').next().unwrap_or(key)
+}
+
+/// Mentions the player a key belongs to.
+pub fn mention(key: &str) -> String {
+    format!("<@{}>", user_of(key))
+}
+
+/// Whether each guild seen so far keeps its own economy.
+pub struct ScopeCache;
+
+impl TypeMapKey for ScopeCache {
+    type Value = Arc<RwLock<HashMap<GuildId, bool>>>;
+}
+
+async fn cache(ctx: &Context) -> Arc<RwLock<HashMap<GuildId, bool>>> {
+    ctx.data.read().await.get::<ScopeCache>().cloned().expect("Expected ScopeCache in TypeMap.")
+}
+
+/// The scope of commands used in a guild, or of direct messages. Fails if the guild's
+/// settings cannot be read, since guessing would send an isolated guild's cards to the
+/// global economy.
+pub async fn scope(ctx: &Context, guild_id: Option<GuildId>) -> Result<Scope, Localized> {
+    let guild_id = match guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(Scope::Global),
+    };
+    let cache = cache(ctx).await;
+    let cached = cache.read().await.get(&guild_id).copied();
+    let isolated = match cached {
+        Some(isolated) => isolated,
+        None => {
+            let isolated = firebase::get_guild_settings(guild_id.to_string()).await?.isolated;
+            cache.write().await.insert(guild_id, isolated);
+            isolated
+        }
+    };
+    Ok(if isolated { Scope::Guild(guild_id) } else { Scope::Global })
+}
+
+/// Switches a guild between its own economy and the global one. Nothing is moved: players
+/// find the collections they had in the mode they switch back to.
+pub async fn set_isolated(ctx: &Context, guild_id: GuildId, isolated: bool) -> Result<(), Localized> {
+    firebase::set_guild_setting(guild_id.to_string(), "isolated", json!({ "booleanValue": isolated })).await?;
+    cache(ctx).await.write().await.insert(guild_id, isolated);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUILD: GuildId = GuildId(111);
+    const OTHER_GUILD: GuildId = GuildId(222);
+    const USER: UserId = UserId(42);
+
+    #[test]
+    fn key_prefixes_the_guild_only_when_isolated() {
+        assert_eq!(Scope::Global.key(USER), "42");
+        assert_eq!(Scope::Guild(GUILD).key(USER), "111_42");
+    }
+
+    #[test]
+    fn contains_tells_global_and_guild_keys_apart() {
+        assert!(Scope::Global.contains("42"));
+        assert!(!Scope::Global.contains("111_42"));
+        assert!(Scope::Guild(GUILD).contains("111_42"));
+        assert!(!Scope::Guild(GUILD).contains("42"));
+    }
+
+    #[test]
+    fn contains_rejects_another_guilds_keys() {
+        assert!(!Scope::Guild(GUILD).contains(&Scope::Guild(OTHER_GUILD).key(USER)));
+        assert!(!Scope::Guild(GuildId(11)).contains("111_42"));
+        assert!(!Scope::Guild(GUILD).contains("1111_42"));
+    }
+
+    #[test]
+    fn user_of_reads_the_user_from_either_key() {
+        assert_eq!(user_of(&Scope::Global.key(USER)), "42");
+        assert_eq!(user_of(&Scope::Guild(GUILD).key(USER)), "42");
+        assert_eq!(mention("111_42"), "<@42>");
+    }
+
+    #[test]
+    fn circulation_keeps_only_holders_in_scope() {
+        let stats = CardStats {
+            total: 6,
+            holders: vec![("111_42".to_string(), 3), ("42".to_string(), 2), ("222_7".to_string(), 1)],
+            first_roller: Some("42".to_string()),
+            first_rolled: None,
+        };
+        let guild = Scope::Guild(GUILD).circulation(stats.clone());
+        assert_eq!(guild.total, 3);
+        assert_eq!(guild.holders, vec![("111_42".to_string(), 3)]);
+        assert_eq!(guild.first_roller, None);
+        let global = Scope::Global.circulation(stats);
+        assert_eq!(global.total, 2);
+        assert_eq!(global.first_roller.as_deref(), Some("42"));
+    }
+}